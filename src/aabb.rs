@@ -0,0 +1,102 @@
+use nalgebra as na;
+
+use crate::ray;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: na::Point3<f32>, max: na::Point3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // Slab test. The interval is closed so that rays grazing a face and boxes
+    // that are flat along one axis still count as hits. A ray parallel to a
+    // slab that starts on its boundary gives NaN, which `max`/`min` ignore.
+    pub fn hit(&self, ray: &ray::Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction[a];
+            let mut t0 = (self.min[a] - ray.orig[a]) * inv_d;
+            let mut t1 = (self.max[a] - ray.orig[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn centroid(&self) -> na::Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn surrounding_box(a: Aabb, b: Aabb) -> Aabb {
+        Aabb {
+            min: a.min.inf(&b.min),
+            max: a.max.sup(&b.max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ray;
+
+    fn unit() -> Aabb {
+        Aabb::new(
+            na::Point3::new(0.0, 0.0, 0.0),
+            na::Point3::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn hit_and_miss() {
+        let through = ray([0.5, 0.5, -1.0], [0.0, 0.0, 1.0]);
+        assert!(unit().hit(&through, 0.0, f32::INFINITY));
+        // The box lies beyond t_max, or behind the ray.
+        assert!(!unit().hit(&through, 0.0, 0.5));
+        assert!(!unit().hit(&ray([0.5, 0.5, 2.0], [0.0, 0.0, 1.0]), 0.0, f32::INFINITY));
+        let beside = ray([1.5, 0.5, -1.0], [0.0, 0.0, 1.0]);
+        assert!(!unit().hit(&beside, 0.0, f32::INFINITY));
+        let diagonal = ray([-1.0, -1.0, 0.5], [1.0, 3.0, 0.0]);
+        assert!(!unit().hit(&diagonal, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn grazing_ray_along_face_hits() {
+        // In the plane of the face x = 1, parallel to it.
+        let along = ray([1.0, 0.5, -1.0], [0.0, 0.0, 1.0]);
+        assert!(unit().hit(&along, 0.0, f32::INFINITY));
+        // Along an edge, where two faces meet.
+        let edge = ray([0.0, 0.0, -1.0], [0.0, 0.0, 1.0]);
+        assert!(unit().hit(&edge, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn zero_thickness_box_hits() {
+        // Flat in y, as the box of an axis-aligned rectangle is.
+        let flat = Aabb::new(
+            na::Point3::new(0.0, 0.5, 0.0),
+            na::Point3::new(1.0, 0.5, 1.0),
+        );
+        let down = ray([0.5, 2.0, 0.5], [0.0, -1.0, 0.0]);
+        assert!(flat.hit(&down, 0.0, f32::INFINITY));
+        let slanted = ray([-1.0, 1.5, 0.5], [1.0, -0.5, 0.0]);
+        assert!(flat.hit(&slanted, 0.0, f32::INFINITY));
+        let past = ray([1.5, 2.0, 0.5], [0.0, -1.0, 0.0]);
+        assert!(!flat.hit(&past, 0.0, f32::INFINITY));
+    }
+}
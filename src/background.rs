@@ -0,0 +1,105 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::color;
+use crate::environment::EnvironmentMap;
+use crate::image_texture::Filter;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub enum Background {
+    Solid(color::Color),
+    // Blends from `bottom` for rays pointing straight down to `top` for rays
+    // pointing straight up, optionally with a sun in front of it.
+    Gradient {
+        top: color::Color,
+        bottom: color::Color,
+        #[serde(default)]
+        sun: Option<Sun>,
+    },
+    Environment(EnvironmentMap),
+}
+
+// A disk of uniform brightness in the sky, which lights the scene from one
+// direction through the rays that happen to reach it. The smaller it is the
+// noisier the lighting, since fewer rays find it.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Sun {
+    // Towards the sun; it does not need to be normalized.
+    pub direction: na::Vector3<f32>,
+    // In degrees. The real sun is about 0.27 degrees across its radius.
+    pub angular_radius: f32,
+    pub intensity: color::Color,
+}
+
+impl Sun {
+    fn covers(&self, direction: na::Vector3<f32>) -> bool {
+        direction.angle(&self.direction) <= self.angular_radius.to_radians()
+    }
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Gradient {
+            top: color::Color::new(0.5, 0.7, 1.0),
+            bottom: color::Color::new(1.0, 1.0, 1.0),
+            sun: None,
+        }
+    }
+}
+
+impl Background {
+    // Colour seen by a ray escaping the scene in the (unit) `direction`.
+    pub fn value(&self, direction: na::Vector3<f32>, filter: Filter) -> color::Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { sun: Some(sun), .. } if sun.covers(direction) => sun.intensity,
+            Background::Gradient { top, bottom, .. } => {
+                color::Color::lerp(*bottom, *top, 0.5 * (direction[1] + 1.0))
+            }
+            Background::Environment(map) => map.value(direction, filter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_shows_within_its_radius() {
+        let sky = Background::Gradient {
+            top: color::Color::new(0.0, 0.0, 1.0),
+            bottom: color::Color::new(1.0, 1.0, 1.0),
+            sun: Some(Sun {
+                direction: na::Vector3::new(0.0, 1.0, 1.0),
+                angular_radius: 5.0,
+                intensity: color::Color::new(50.0, 45.0, 40.0),
+            }),
+        };
+        let towards = |degrees: f32| {
+            let angle = degrees.to_radians();
+            na::Vector3::new(0.0, angle.sin(), angle.cos())
+        };
+        assert_eq!(
+            sky.value(towards(45.0), Filter::Bilinear),
+            color::Color::new(50.0, 45.0, 40.0)
+        );
+        assert_eq!(
+            sky.value(towards(49.0), Filter::Bilinear),
+            color::Color::new(50.0, 45.0, 40.0)
+        );
+        assert_eq!(
+            sky.value(na::Vector3::y(), Filter::Bilinear),
+            color::Color::new(0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            sky.value(towards(51.0), Filter::Bilinear),
+            Background::Gradient {
+                top: color::Color::new(0.0, 0.0, 1.0),
+                bottom: color::Color::new(1.0, 1.0, 1.0),
+                sun: None,
+            }
+            .value(towards(51.0), Filter::Bilinear)
+        );
+    }
+}
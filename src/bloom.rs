@@ -0,0 +1,93 @@
+use crate::color::Color;
+
+// Glow around bright pixels: the part of each pixel brighter than `threshold`
+// is blurred with a Gaussian reaching `radius` pixels out and added back on
+// top of the image.
+#[derive(Clone, Copy)]
+pub struct Bloom {
+    pub threshold: f32,
+    pub radius: u32,
+}
+
+impl Bloom {
+    // Normalized weights for offsets -radius..=radius, with the kernel edge at
+    // three standard deviations.
+    fn kernel(&self) -> Vec<f32> {
+        let r = self.radius as i64;
+        let sigma = (self.radius as f32 / 3.0).max(0.5);
+        let weights: Vec<f32> = (-r..=r)
+            .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f32 = weights.iter().sum();
+        weights.iter().map(|w| w / total).collect()
+    }
+
+    // One pass of the separable blur along rows (`step` 1) or columns (`step`
+    // the image width), clamping at the image edges.
+    fn blur(
+        layer: &[Color],
+        kernel: &[f32],
+        len: usize,
+        lines: usize,
+        step: usize,
+        stride: usize,
+    ) -> Vec<Color> {
+        let r = (kernel.len() / 2) as i64;
+        let mut out = vec![Color::new(0.0, 0.0, 0.0); layer.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                for (k, w) in kernel.iter().enumerate() {
+                    let j = (i as i64 + k as i64 - r).clamp(0, len as i64 - 1) as usize;
+                    sum += *w * layer[line * stride + j * step];
+                }
+                out[line * stride + i * step] = sum;
+            }
+        }
+        out
+    }
+
+    pub fn apply(&self, pixels: &mut [Color], width: u32, height: u32) {
+        let (w, h) = (width as usize, height as usize);
+        // Keep only what is over the threshold, so the glow fades in rather
+        // than switching on at it.
+        let bright: Vec<Color> = pixels
+            .iter()
+            .map(|&c| {
+                let brightness = c.luminance();
+                // Black pixels have nothing to scale, even under a negative
+                // threshold.
+                if brightness > self.threshold && brightness > 0.0 {
+                    c * ((brightness - self.threshold) / brightness)
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                }
+            })
+            .collect();
+
+        let kernel = self.kernel();
+        let rows = Bloom::blur(&bright, &kernel, w, h, 1, w);
+        let glow = Bloom::blur(&rows, &kernel, h, w, w, 1);
+        for (p, g) in pixels.iter_mut().zip(glow) {
+            *p += g;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn black_pixels_add_no_glow() {
+        let mut pixels = vec![Color::new(0.0, 0.0, 0.0); 9];
+        pixels[4] = Color::new(2.0, 2.0, 2.0);
+        Bloom {
+            threshold: -0.5,
+            radius: 1,
+        }
+        .apply(&mut pixels, 3, 3);
+        assert!(pixels.iter().all(|p| p.r().is_finite()));
+        assert!(pixels[0].r() > 0.0 && pixels[4].r() > 2.0);
+    }
+}
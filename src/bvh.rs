@@ -0,0 +1,322 @@
+use std::cmp::Ordering;
+
+use crate::aabb::Aabb;
+use crate::primitive::Primitive;
+use crate::ray;
+use crate::{HitRecord, Object};
+
+// The scene as seen by the renderer: bounded objects live in a BVH while
+// objects without a bounding box (e.g. planes) are tested one by one.
+pub struct World {
+    root: Option<BVHNode>,
+    unbounded: Vec<Primitive>,
+}
+
+impl World {
+    pub fn new(objects: Vec<Primitive>) -> World {
+        World::with_split(objects, Split::Median)
+    }
+
+    pub fn with_split(objects: Vec<Primitive>, split: Split) -> World {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = objects
+            .into_iter()
+            .partition(|o| o.bounding_box().is_some());
+        World {
+            root: if bounded.is_empty() {
+                None
+            } else {
+                Some(BVHNode::new(bounded, split))
+            },
+            unbounded,
+        }
+    }
+
+    // The box around the objects that have one, leaving out unbounded ones
+    // such as planes. None if there are no bounded objects.
+    pub fn bounded_box(&self) -> Option<Aabb> {
+        self.root.as_ref().map(|root| root.bbox())
+    }
+
+    // The boxes of the bounded objects, or with `branches` those of the
+    // tree's inner nodes, which show how it divides the scene.
+    pub fn boxes(&self, branches: bool) -> Vec<Aabb> {
+        let mut boxes = Vec::new();
+        if let Some(root) = &self.root {
+            root.collect_boxes(branches, &mut boxes);
+        }
+        boxes
+    }
+}
+
+impl Object for World {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let tree_hit = self
+            .root
+            .as_ref()
+            .and_then(|root| root.intersect(ray, t_min, t_max));
+        let flat_hit = self.unbounded[..].intersect(ray, t_min, tree_hit.map_or(t_max, |h| h.t));
+        flat_hit.or(tree_hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(|root| root.bbox())
+    }
+}
+
+// How a BVH node divides its objects between its two children.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Split {
+    // Sort along the axis in which the centroids are spread the most and cut
+    // the list in half.
+    Median,
+    // Try every cut of the objects sorted along each axis and keep the one
+    // with the lowest surface area heuristic cost, the expected number of
+    // objects a ray passing through the node has to test. This pays off
+    // when object sizes vary a lot, where halving the list can leave a huge
+    // object's box overlapping everything.
+    SurfaceArea,
+}
+
+impl std::str::FromStr for Split {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Split, ()> {
+        match s {
+            "median" => Ok(Split::Median),
+            "sah" => Ok(Split::SurfaceArea),
+            _ => Err(()),
+        }
+    }
+}
+
+// With this few objects the surface area heuristic has little to choose
+// between, so the median split is used instead.
+const SAH_MIN_OBJECTS: usize = 4;
+
+pub enum BVHNode {
+    Leaf(Primitive),
+    Branch {
+        left: Box<BVHNode>,
+        right: Box<BVHNode>,
+        bbox: Aabb,
+    },
+}
+
+fn bbox_of(object: &Primitive) -> Aabb {
+    object
+        .bounding_box()
+        .expect("objects in a BVH must have a bounding box")
+}
+
+impl BVHNode {
+    // Recursively splits the objects in two with `split` until each leaf
+    // holds one.
+    pub fn new(mut objects: Vec<Primitive>, split: Split) -> BVHNode {
+        assert!(!objects.is_empty(), "cannot build a BVH without objects");
+        if objects.len() == 1 {
+            return BVHNode::Leaf(objects.pop().unwrap());
+        }
+
+        let cut = if split == Split::SurfaceArea && objects.len() > SAH_MIN_OBJECTS {
+            sah_order(&mut objects)
+        } else {
+            median_order(&mut objects)
+        };
+        let rest = objects.split_off(cut);
+        let left = Box::new(BVHNode::new(objects, split));
+        let right = Box::new(BVHNode::new(rest, split));
+        let bbox = Aabb::surrounding_box(left.bbox(), right.bbox());
+        BVHNode::Branch { left, right, bbox }
+    }
+
+    fn bbox(&self) -> Aabb {
+        match self {
+            BVHNode::Leaf(object) => bbox_of(object),
+            BVHNode::Branch { bbox, .. } => *bbox,
+        }
+    }
+
+    fn collect_boxes(&self, branches: bool, boxes: &mut Vec<Aabb>) {
+        match self {
+            BVHNode::Leaf(object) if !branches => boxes.push(bbox_of(object)),
+            BVHNode::Leaf(_) => {}
+            BVHNode::Branch { left, right, bbox } => {
+                if branches {
+                    boxes.push(*bbox);
+                }
+                left.collect_boxes(branches, boxes);
+                right.collect_boxes(branches, boxes);
+            }
+        }
+    }
+}
+
+// Sorts the objects along the axis in which their centroids are spread the
+// most and returns the index of the middle one, where they are cut in two.
+fn median_order(objects: &mut [Primitive]) -> usize {
+    let centroids = objects
+        .iter()
+        .map(|o| bbox_of(o).centroid())
+        .map(|c| Aabb::new(c, c))
+        .reduce(Aabb::surrounding_box)
+        .unwrap();
+    let extent = centroids.max - centroids.min;
+    let axis = extent.imax();
+    objects.sort_by(|a, b| {
+        let ca = bbox_of(a).centroid()[axis];
+        let cb = bbox_of(b).centroid()[axis];
+        ca.partial_cmp(&cb).unwrap_or(Ordering::Equal)
+    });
+    objects.len() / 2
+}
+
+// Sorts the objects along the axis with the cheapest cut by the surface
+// area heuristic and returns the index of that cut. A cut leaving `l` objects
+// in a box of area `al` on one side and `r` in `ar` on the other costs
+// `l * al + r * ar`; dividing by the parent's area would not change which is
+// cheapest.
+fn sah_order(objects: &mut Vec<Primitive>) -> usize {
+    let boxes: Vec<Aabb> = objects.iter().map(bbox_of).collect();
+    let n = boxes.len();
+    let orders: Vec<Vec<usize>> = (0..3)
+        .map(|axis| {
+            let mut order: Vec<usize> = (0..n).collect();
+            order.sort_by(|&a, &b| {
+                let ca = boxes[a].centroid()[axis];
+                let cb = boxes[b].centroid()[axis];
+                ca.partial_cmp(&cb).unwrap_or(Ordering::Equal)
+            });
+            order
+        })
+        .collect();
+
+    // Boxes so large that every cost is infinite or NaN leave the median.
+    let (mut best_cost, mut best_axis, mut best_cut) = (f32::INFINITY, 0, n / 2);
+    for (axis, order) in orders.iter().enumerate() {
+        // right_areas[i] is the area of the box around order[i..].
+        let mut right_areas = vec![0.0; n];
+        let mut right = boxes[order[n - 1]];
+        for i in (1..n).rev() {
+            right = Aabb::surrounding_box(right, boxes[order[i]]);
+            right_areas[i] = right.surface_area();
+        }
+        let mut left = boxes[order[0]];
+        for cut in 1..n {
+            let cost = left.surface_area() * cut as f32 + right_areas[cut] * (n - cut) as f32;
+            if cost < best_cost {
+                (best_cost, best_axis, best_cut) = (cost, axis, cut);
+            }
+            left = Aabb::surrounding_box(left, boxes[order[cut]]);
+        }
+    }
+
+    let mut slots: Vec<Option<Primitive>> = objects.drain(..).map(Some).collect();
+    objects.extend(orders[best_axis].iter().map(|&i| slots[i].take().unwrap()));
+    best_cut
+}
+
+impl Object for BVHNode {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        match self {
+            BVHNode::Leaf(object) => object.intersect(ray, t_min, t_max),
+            BVHNode::Branch { left, right, bbox } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.intersect(ray, t_min, t_max);
+                let right_hit = right.intersect(ray, t_min, left_hit.map_or(t_max, |h| h.t));
+                right_hit.or(left_hit)
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::grey;
+    use crate::{Sphere, T_MIN};
+    use nalgebra as na;
+    use rand::{Rng, SeedableRng};
+
+    // A few huge spheres among many tiny ones, the case where halving the
+    // list builds poor trees. The spheres are the same for every split.
+    fn mixed_spheres(split: Split) -> World {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(3);
+        let material = grey();
+        let objects = (0..2000)
+            .map(|i| {
+                let radius = if i % 200 == 0 {
+                    rng.gen_range(3.0, 6.0)
+                } else {
+                    rng.gen_range(0.02, 0.1)
+                };
+                let centre = [
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                ];
+                Primitive::Sphere(Sphere {
+                    centre: centre.into(),
+                    radius,
+                    material,
+                })
+            })
+            .collect();
+        World::with_split(objects, split)
+    }
+
+    // Rays from a ring around the spheres towards points among them.
+    fn rays(count: usize) -> Vec<ray::Ray> {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(4);
+        (0..count)
+            .map(|_| {
+                let angle = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+                let origin = na::Point3::new(40.0 * angle.cos(), 0.0, 40.0 * angle.sin());
+                let target = na::Point3::new(
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                );
+                ray::Ray::new(origin, target - origin, 0.0)
+            })
+            .collect()
+    }
+
+    fn nearest(world: &World, rays: &[ray::Ray]) -> Vec<Option<f32>> {
+        rays.iter()
+            .map(|r| world.intersect(r, T_MIN, f32::INFINITY).map(|hit| hit.t))
+            .collect()
+    }
+
+    #[test]
+    fn surface_area_split_finds_the_same_hits() {
+        let rays = rays(2000);
+        let median = nearest(&mixed_spheres(Split::Median), &rays);
+        let sah = nearest(&mixed_spheres(Split::SurfaceArea), &rays);
+        assert!(sah.iter().filter(|t| t.is_some()).count() > 100);
+        assert_eq!(median, sah);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_surface_area_against_median() {
+        let rays = rays(1_000_000);
+        let mut results = Vec::new();
+        for split in [Split::Median, Split::SurfaceArea] {
+            let world = mixed_spheres(split);
+            let start = std::time::Instant::now();
+            results.push(nearest(&world, &rays));
+            println!("{:?}: {:?}", split, start.elapsed());
+        }
+        assert_eq!(results[0], results[1]);
+    }
+}
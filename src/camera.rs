@@ -0,0 +1,350 @@
+use nalgebra as na;
+use rand::Rng;
+
+use crate::aabb;
+use crate::ray;
+
+#[derive(Clone, Copy, Debug)]
+enum Projection {
+    // Rays fan out from `origin` through the viewport.
+    Perspective,
+    // Rays start across the viewport and all travel in this direction.
+    Orthographic(na::Vector3<f32>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    projection: Projection,
+    origin: na::Point3<f32>,
+    lower_left_corner: na::Point3<f32>,
+    horizontal: na::Vector3<f32>,
+    vertical: na::Vector3<f32>,
+    u: na::Vector3<f32>,
+    v: na::Vector3<f32>,
+    lens_radius: f32,
+    time0: f32,
+    time1: f32,
+}
+
+fn random_in_unit_disk(rng: &mut rand::rngs::SmallRng) -> na::Vector2<f32> {
+    loop {
+        let p = na::Vector2::new(rng.gen_range(-1f32, 1f32), rng.gen_range(-1f32, 1f32));
+        if p.norm_squared() < 1.0 {
+            return p;
+        }
+    }
+}
+
+// Height of a 35 mm film frame, which focal lengths are usually quoted for.
+const FILM_HEIGHT_MM: f32 = 24.0;
+
+// The vertical field of view in degrees of a lens with the given 35 mm
+// equivalent focal length: 50 mm gives about 27 degrees, while a 200 mm
+// telephoto gives under 7.
+pub fn vfov_from_focal_length(focal_length_mm: f32) -> f32 {
+    2.0 * (FILM_HEIGHT_MM / (2.0 * focal_length_mm))
+        .atan()
+        .to_degrees()
+}
+
+// A `look_from` and `look_at` that frame all of `bbox` for a perspective
+// camera with the given field of view and aspect ratio, looking at its centre
+// from `direction`. The camera backs off until the sphere around the box,
+// enlarged by the fraction `margin`, fits the narrower of the two fields of
+// view. A box without size, or without a direction to look from, gives
+// nothing to point the camera along.
+pub fn fit(
+    bbox: &aabb::Aabb,
+    direction: na::Vector3<f32>,
+    vfov: f32,
+    aspect_ratio: f32,
+    margin: f32,
+) -> Result<(na::Point3<f32>, na::Point3<f32>), String> {
+    let extent = bbox.max - bbox.min;
+    let size = extent.norm();
+    if !(size > 0.0 && size.is_finite()) || extent.iter().any(|&e| e < 0.0) {
+        return Err("the scene has no size to fit the camera to".to_string());
+    }
+    if direction.norm_squared() == 0.0 {
+        return Err(
+            "look_from and look_at are the same point, so there is no direction to look from"
+                .to_string(),
+        );
+    }
+    let look_at = bbox.centroid();
+    let radius = (1.0 + margin) * size / 2.0;
+    let half_vfov = (vfov / 2.0).to_radians();
+    let half_hfov = (aspect_ratio * half_vfov.tan()).atan();
+    let distance = radius / half_vfov.min(half_hfov).sin();
+    Ok((look_at + distance * direction.normalize(), look_at))
+}
+
+impl Camera {
+    // `vfov` is the vertical field of view in degrees. The viewport sits
+    // `focus_dist` in front of `look_from` and spans the angle `vfov`, so it is
+    // `2 * focus_dist * tan(vfov / 2)` tall and `aspect_ratio` times that wide;
+    // the horizontal field of view follows from the aspect ratio. Objects at
+    // `focus_dist` are in perfect focus, and an `aperture` of zero gives a
+    // pinhole camera.
+    pub fn new(
+        look_from: na::Point3<f32>,
+        look_at: na::Point3<f32>,
+        vup: na::Vector3<f32>,
+        vfov: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Camera {
+        let viewport_height = 2.0 * (vfov.to_radians() / 2.0).tan();
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let w = (look_from - look_at).normalize();
+        let u = vup.cross(&w).normalize();
+        let v = w.cross(&u);
+
+        let horizontal = focus_dist * viewport_width * u;
+        let vertical = focus_dist * viewport_height * v;
+        Camera {
+            projection: Projection::Perspective,
+            origin: look_from,
+            lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0 - focus_dist * w,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+
+    // A parallel projection looking from `look_from` towards `look_at`, with a
+    // viewport `height` scene units tall centred on `look_from`.
+    pub fn orthographic(
+        look_from: na::Point3<f32>,
+        look_at: na::Point3<f32>,
+        vup: na::Vector3<f32>,
+        height: f32,
+        aspect_ratio: f32,
+    ) -> Camera {
+        let w = (look_from - look_at).normalize();
+        let u = vup.cross(&w).normalize();
+        let v = w.cross(&u);
+
+        let horizontal = aspect_ratio * height * u;
+        let vertical = height * v;
+        Camera {
+            projection: Projection::Orthographic(-w),
+            origin: look_from,
+            lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: 0.0,
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+
+    // Keeps the shutter open from `time0` to `time1`, giving each ray a time
+    // uniformly drawn from that interval. The default is a zero-width shutter
+    // at time 0.
+    pub fn with_shutter(self, time0: f32, time1: f32) -> Camera {
+        Camera {
+            time0,
+            time1,
+            ..self
+        }
+    }
+
+    fn sample_time(&self, rng: &mut rand::rngs::SmallRng) -> f32 {
+        if self.time1 > self.time0 {
+            rng.gen_range(self.time0, self.time1)
+        } else {
+            self.time0
+        }
+    }
+
+    pub fn get_ray(&self, s: f32, t: f32, rng: &mut rand::rngs::SmallRng) -> ray::Ray {
+        let viewport_pt = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        let ray = match self.projection {
+            Projection::Perspective => {
+                let rd = self.lens_radius * random_in_unit_disk(rng);
+                let offset = self.u * rd[0] + self.v * rd[1];
+                ray::Ray::new(
+                    self.origin + offset,
+                    viewport_pt - self.origin - offset,
+                    self.sample_time(rng),
+                )
+            }
+            Projection::Orthographic(direction) => {
+                ray::Ray::new(viewport_pt, direction, self.sample_time(rng))
+            }
+        };
+        ray.with_seed(rng.gen())
+    }
+
+    // The unit vector the camera looks along.
+    fn forward(&self) -> na::Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                let centre = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+                (centre - self.origin).normalize()
+            }
+            Projection::Orthographic(direction) => direction.normalize(),
+        }
+    }
+
+    // How far `point` lies in front of the camera, negative behind it.
+    pub fn depth(&self, point: na::Point3<f32>) -> f32 {
+        (point - self.origin).dot(&self.forward())
+    }
+
+    // The viewport coordinates that `get_pinhole_ray` takes to aim at
+    // `point`, which must be in front of the camera.
+    pub fn project(&self, point: na::Point3<f32>) -> (f32, f32) {
+        let on_viewport = match self.projection {
+            Projection::Perspective => {
+                let centre = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+                let (to_centre, to_point) = (centre - self.origin, point - self.origin);
+                self.origin + to_point * (to_centre.norm_squared() / to_point.dot(&to_centre))
+            }
+            // Every point along the view direction lands on the same spot.
+            Projection::Orthographic(_) => point,
+        };
+        let offset = on_viewport - self.lower_left_corner;
+        (
+            offset.dot(&self.horizontal) / self.horizontal.norm_squared(),
+            offset.dot(&self.vertical) / self.vertical.norm_squared(),
+        )
+    }
+
+    // The ray through the centre of the lens, ignoring depth of field.
+    pub fn get_pinhole_ray(&self, s: f32, t: f32) -> ray::Ray {
+        let viewport_pt = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        match self.projection {
+            Projection::Perspective => {
+                ray::Ray::new(self.origin, viewport_pt - self.origin, self.time0)
+            }
+            Projection::Orthographic(direction) => {
+                ray::Ray::new(viewport_pt, direction, self.time0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focal_length_to_vfov() {
+        assert!((vfov_from_focal_length(50.0) - 26.99).abs() < 0.01);
+        assert!((vfov_from_focal_length(12.0) - 90.0).abs() < 1e-4);
+        assert!(vfov_from_focal_length(200.0) < 7.0);
+    }
+
+    #[test]
+    fn fit_rejects_pointlike_box_and_missing_direction() {
+        let point = na::Point3::new(1.0, 2.0, 3.0);
+        let direction = na::Vector3::z();
+        let pointlike = aabb::Aabb::new(point, point);
+        assert!(fit(&pointlike, direction, 40.0, 1.0, 0.0).is_err());
+        let inverted = aabb::Aabb::new(point, na::Point3::origin());
+        assert!(fit(&inverted, direction, 40.0, 1.0, 0.0).is_err());
+        let bbox = aabb::Aabb::new(na::Point3::origin(), point);
+        assert!(fit(&bbox, na::Vector3::zeros(), 40.0, 1.0, 0.0).is_err());
+        assert!(fit(&bbox, direction, 40.0, 1.0, 0.0).is_ok());
+    }
+
+    #[test]
+    fn fit_keeps_box_in_view() {
+        let bbox = aabb::Aabb::new(
+            na::Point3::new(-1.0, 0.0, -3.0),
+            na::Point3::new(2.0, 1.0, 0.0),
+        );
+        for &aspect_ratio in &[0.5, 1.0, 2.0] {
+            let (look_from, look_at) = fit(
+                &bbox,
+                na::Vector3::new(1.0, 1.0, 2.0),
+                40.0,
+                aspect_ratio,
+                0.0,
+            )
+            .unwrap();
+            assert_eq!(look_at, na::Point3::new(0.5, 0.5, -1.5));
+            let camera = Camera::new(
+                look_from,
+                look_at,
+                na::Vector3::y(),
+                40.0,
+                aspect_ratio,
+                0.0,
+                1.0,
+            );
+            let w = (look_from - look_at).normalize();
+            let half_height = (20.0f32).to_radians().tan();
+            for i in 0..8 {
+                let corner = na::Point3::new(
+                    if i & 1 == 0 { bbox.min[0] } else { bbox.max[0] },
+                    if i & 2 == 0 { bbox.min[1] } else { bbox.max[1] },
+                    if i & 4 == 0 { bbox.min[2] } else { bbox.max[2] },
+                );
+                let d = corner - look_from;
+                let depth = -d.dot(&w);
+                assert!(d.dot(&camera.u).abs() / depth <= aspect_ratio * half_height);
+                assert!(d.dot(&camera.v).abs() / depth <= half_height);
+            }
+        }
+    }
+
+    #[test]
+    fn project_inverts_pinhole_rays() {
+        let perspective = Camera::new(
+            na::Point3::new(1.0, 2.0, 3.0),
+            na::Point3::new(0.0, 0.5, -1.0),
+            na::Vector3::y(),
+            50.0,
+            1.5,
+            0.0,
+            2.0,
+        );
+        let orthographic = Camera::orthographic(
+            na::Point3::new(1.0, 2.0, 3.0),
+            na::Point3::new(0.0, 0.5, -1.0),
+            na::Vector3::y(),
+            4.0,
+            1.5,
+        );
+        for camera in &[perspective, orthographic] {
+            for &(s, t) in &[(0.5, 0.5), (0.1, 0.9), (1.2, -0.3)] {
+                let point = camera.get_pinhole_ray(s, t).at(7.0);
+                assert!(camera.depth(point) > 0.0);
+                let (ps, pt) = camera.project(point);
+                assert!((ps - s).abs() < 1e-4 && (pt - t).abs() < 1e-4);
+            }
+        }
+        let behind = perspective.get_pinhole_ray(0.5, 0.5).at(-1.0);
+        assert!(perspective.depth(behind) < 0.0);
+    }
+
+    #[test]
+    fn viewport_spans_vfov() {
+        let camera = Camera::new(
+            na::Point3::origin(),
+            na::Point3::new(0.0, 0.0, -1.0),
+            na::Vector3::y(),
+            90.0,
+            2.0,
+            0.0,
+            3.0,
+        );
+        // At 90 degrees the viewport is twice as tall as it is far away.
+        assert!((camera.vertical.norm() - 6.0).abs() < 1e-5);
+        assert!((camera.horizontal.norm() - 12.0).abs() < 1e-5);
+        let top = camera.get_pinhole_ray(0.5, 1.0);
+        let angle = top.direction.angle(&na::Vector3::new(0.0, 0.0, -1.0));
+        assert!((angle.to_degrees() - 45.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,296 @@
+use std::str::FromStr;
+
+pub const DEFAULT_MAX_DEPTH: u32 = 20;
+pub const DEFAULT_GAMMA: f32 = 2.0;
+pub const DEFAULT_ADAPTIVE_MIN_SAMPLES: u32 = 16;
+pub const DEFAULT_BLOOM_RADIUS: u32 = 8;
+pub const DEFAULT_DENOISE_RADIUS: u32 = 3;
+pub const DEFAULT_EDGE_SAMPLES: u32 = 256;
+// Extra room left around the scene by --fit-camera, as a fraction of its size.
+pub const FIT_CAMERA_MARGIN: f32 = 0.05;
+
+#[derive(Default)]
+pub struct Args {
+    pub scenes: Vec<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub output: Option<String>,
+    pub seed: Option<u64>,
+    pub tone_map: bool,
+    pub gamma: Option<f32>,
+    pub exposure: Option<f32>,
+    pub gains: Option<crate::color::Color>,
+    pub mode: Option<crate::RenderMode>,
+    pub sampler: Option<crate::Sampler>,
+    pub adaptive: Option<f32>,
+    pub adaptive_min: Option<u32>,
+    pub firefly_clamp: Option<f32>,
+    pub diffuse: Option<crate::DiffuseMode>,
+    pub roulette: Option<u32>,
+    pub bvh: Option<crate::bvh::Split>,
+    pub min_bounces: Option<u32>,
+    pub preview: bool,
+    pub quiet: bool,
+    pub threads: Option<usize>,
+    pub binary_ppm: bool,
+    pub transparent: bool,
+    pub nearest_textures: bool,
+    pub bloom: Option<f32>,
+    pub bloom_radius: Option<u32>,
+    pub denoise: Option<f32>,
+    pub denoise_radius: Option<u32>,
+    pub edges: Option<f32>,
+    pub edge_samples: Option<u32>,
+    pub frames: Option<u32>,
+    pub checkpoint: Option<u32>,
+    pub resume: bool,
+    pub merge: Vec<String>,
+    pub fit_camera: bool,
+    pub wireframe: Option<crate::wireframe::Wireframe>,
+    pub depth: Option<String>,
+    pub aovs: bool,
+    pub sample_map: bool,
+    pub depth_near: Option<f32>,
+    pub depth_far: Option<f32>,
+    pub depth_invert: bool,
+    pub help: bool,
+}
+
+pub fn usage() -> String {
+    format!(
+        "Usage: raytracer [OPTIONS] <scene.json>...
+
+Options override the values given in the scene file.
+    --width <N>       image width in pixels (default: scene, or {width})
+    --height <N>      image height in pixels (default: scene, or {height})
+    --samples <N>     samples per pixel (default: scene, or {samples})
+    --preview         one ray through each pixel centre, without anti-aliasing or
+                      depth of field, overriding --samples
+    --max-depth <N>   maximum number of bounces per ray (default: {max_depth})
+    --output <FILE>   output image, .png or .ppm, or - for a PPM on stdout (default: scene,
+                      or {output})
+    --binary-ppm      write .ppm output as binary P6 instead of plain-text P3
+    --transparent     leave pixels where nothing is hit transparent; needs .png output
+    --nearest-textures
+                      look up image textures and environment maps without bilinear
+                      filtering
+    --seed <N>        seed for a reproducible render (default: random)
+    --tone-map        apply Reinhard tone mapping instead of clipping
+    --gamma <G>       display gamma used for the output (default: {gamma})
+    --exposure <E>    multiply the image by E before tone mapping and gamma (default: 1)
+    --gains <R,G,B>   also multiply each channel by its gain, to balance white (default:
+                      1,1,1)
+    --bloom <T>       add a glow around pixels brighter than T (default: off)
+    --bloom-radius <N>
+                      how far the glow spreads, in pixels (default: {bloom_radius})
+    --denoise <S>     smooth noise while keeping edges, averaging only neighbours whose
+                      colours differ by about S or less (default: off)
+    --denoise-radius <N>
+                      how far the denoiser looks, in pixels (default: {denoise_radius})
+    --mode <MODE>     `shaded`, or `normals` to show surface normals (default: shaded)
+    --sampler <S>     `random`, `stratified` for a jittered grid, or `cmj` for correlated
+                      multi-jittered samples, spread evenly along each axis as well
+                      (default: random)
+    --adaptive <E>    stop sampling a pixel once the standard error of its colour is
+                      below E, so --samples becomes a maximum (default: off)
+    --adaptive-min <N>
+                      samples per pixel before --adaptive may stop (default: {adaptive_min})
+    --edges <T>       after --samples, sample pixels whose colour differs from a
+                      neighbour's by more than T again, up to --edge-samples (default: off)
+    --edge-samples <N>
+                      samples per pixel for pixels on edges (default: {edge_samples})
+    --firefly-clamp <L>
+                      scale down samples brighter than L before averaging (default: off)
+    --diffuse <D>     diffuse bounces: `unit-vector`, `cosine` for exact cosine-weighted
+                      sampling, or `hemisphere` for uniform directions, which shades
+                      flatter than Lambertian (default: unit-vector)
+    --roulette <N>    randomly end paths by their throughput after N bounces (default: off)
+    --min-bounces <N> only count light from the background once a path has bounced N
+                      times; earlier escapes add black (default: 0)
+    --bvh <SPLIT>     how the BVH over the scene's objects divides them: `median`, or `sah`
+                      for the surface area heuristic, which is slower to build but
+                      faster to trace when object sizes vary a lot (default: median)
+    --checkpoint <N>  save the render to <output>.checkpoint every N samples per pixel;
+                      the file is kept afterwards, and is not overwritten unless resuming
+    --resume          with --checkpoint, add --samples more samples to <output>.checkpoint
+                      instead of starting a new render; the `stratified` and `cmj`
+                      samplers lay samples out by each run's --samples, so the image is
+                      not quite the same as one run of all of them
+    --merge <FILE>    instead of rendering, average the checkpoints of runs with different
+                      seeds into one image, weighted by their samples; give it once per
+                      file, and add --checkpoint to keep the merged checkpoint
+    --frames <N>      render an animation of N frames, numbering the output files
+                      (default: scene, or 1)
+    --fit-camera      move the camera to frame every object except unbounded ones like
+                      planes, keeping the direction it looks from
+    --wireframe <W>   outline boxes over the image: `objects` for each object's bounding
+                      box, or `bvh` for the boxes the BVH divides the scene into
+    --depth <FILE>    also write the first-hit distance as a grayscale image
+    --depth-near <T>  distance drawn black in the depth image (default: nearest hit)
+    --depth-far <T>   distance drawn white, and used for misses (default: 95th percentile hit)
+    --depth-invert    draw near as white and far as black instead
+    --aovs            also write the first-hit normals and albedo, as external denoisers
+                      take them, to <output>_normal and <output>_albedo
+    --sample-map      also write how many samples each pixel took, relative to the most
+                      any pixel took, from black through red and yellow to white, to
+                      <output>_samples
+    --threads <N>     number of render threads; the image does not depend on it
+                      (default: one per core)
+    --quiet           don't show render progress
+    --help            print this message",
+        width = crate::scene::DEFAULT_WIDTH,
+        height = crate::scene::DEFAULT_HEIGHT,
+        samples = crate::scene::DEFAULT_SAMPLES_PER_PIXEL,
+        max_depth = DEFAULT_MAX_DEPTH,
+        output = crate::scene::DEFAULT_OUTPUT,
+        gamma = DEFAULT_GAMMA,
+        adaptive_min = DEFAULT_ADAPTIVE_MIN_SAMPLES,
+        bloom_radius = DEFAULT_BLOOM_RADIUS,
+        denoise_radius = DEFAULT_DENOISE_RADIUS,
+        edge_samples = DEFAULT_EDGE_SAMPLES,
+    )
+}
+
+fn value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for {}", value, flag))
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => parsed.width = Some(value(&arg, args.next())?),
+                "--height" => parsed.height = Some(value(&arg, args.next())?),
+                "--samples" => parsed.samples = Some(value(&arg, args.next())?),
+                "--preview" => parsed.preview = true,
+                "--max-depth" => parsed.max_depth = Some(value(&arg, args.next())?),
+                "--output" => parsed.output = Some(value(&arg, args.next())?),
+                "--seed" => parsed.seed = Some(value(&arg, args.next())?),
+                "--binary-ppm" => parsed.binary_ppm = true,
+                "--transparent" => parsed.transparent = true,
+                "--nearest-textures" => parsed.nearest_textures = true,
+                "--bloom" => parsed.bloom = Some(value(&arg, args.next())?),
+                "--bloom-radius" => parsed.bloom_radius = Some(value(&arg, args.next())?),
+                "--denoise" => parsed.denoise = Some(value(&arg, args.next())?),
+                "--denoise-radius" => parsed.denoise_radius = Some(value(&arg, args.next())?),
+                "--tone-map" => parsed.tone_map = true,
+                "--gamma" => parsed.gamma = Some(value(&arg, args.next())?),
+                "--exposure" => parsed.exposure = Some(value(&arg, args.next())?),
+                "--gains" => parsed.gains = Some(value(&arg, args.next())?),
+                "--mode" => parsed.mode = Some(value(&arg, args.next())?),
+                "--sampler" => parsed.sampler = Some(value(&arg, args.next())?),
+                "--adaptive" => parsed.adaptive = Some(value(&arg, args.next())?),
+                "--adaptive-min" => parsed.adaptive_min = Some(value(&arg, args.next())?),
+                "--edges" => parsed.edges = Some(value(&arg, args.next())?),
+                "--edge-samples" => parsed.edge_samples = Some(value(&arg, args.next())?),
+                "--firefly-clamp" => parsed.firefly_clamp = Some(value(&arg, args.next())?),
+                "--diffuse" => parsed.diffuse = Some(value(&arg, args.next())?),
+                "--roulette" => parsed.roulette = Some(value(&arg, args.next())?),
+                "--min-bounces" => parsed.min_bounces = Some(value(&arg, args.next())?),
+                "--frames" => parsed.frames = Some(value(&arg, args.next())?),
+                "--bvh" => parsed.bvh = Some(value(&arg, args.next())?),
+                "--checkpoint" => parsed.checkpoint = Some(value(&arg, args.next())?),
+                "--resume" => parsed.resume = true,
+                "--merge" => parsed.merge.push(value(&arg, args.next())?),
+                "--fit-camera" => parsed.fit_camera = true,
+                "--wireframe" => parsed.wireframe = Some(value(&arg, args.next())?),
+                "--depth" => parsed.depth = Some(value(&arg, args.next())?),
+                "--depth-near" => parsed.depth_near = Some(value(&arg, args.next())?),
+                "--depth-far" => parsed.depth_far = Some(value(&arg, args.next())?),
+                "--depth-invert" => parsed.depth_invert = true,
+                "--aovs" => parsed.aovs = true,
+                "--sample-map" => parsed.sample_map = true,
+                "--quiet" | "-q" => parsed.quiet = true,
+                "--threads" => parsed.threads = Some(value(&arg, args.next())?),
+                "--help" | "-h" => parsed.help = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+                _ => parsed.scenes.push(arg),
+            }
+        }
+
+        if parsed.output.is_some() && parsed.scenes.len() > 1 {
+            return Err("--output can only be used with a single scene".to_string());
+        }
+        if !parsed.merge.is_empty() && parsed.scenes.len() > 1 {
+            return Err("--merge can only be used with a single scene".to_string());
+        }
+        if parsed.depth.is_some() && parsed.scenes.len() > 1 {
+            return Err("--depth can only be used with a single scene".to_string());
+        }
+        if parsed.aovs && parsed.output.as_deref() == Some("-") {
+            return Err("--aovs needs an output file, not stdout".to_string());
+        }
+        if parsed.sample_map && parsed.output.as_deref() == Some("-") {
+            return Err("--sample-map needs an output file, not stdout".to_string());
+        }
+        if parsed.frames == Some(0) {
+            return Err("--frames must be at least 1".to_string());
+        }
+        if parsed.frames.is_some_and(|frames| frames > 1) && parsed.output.as_deref() == Some("-") {
+            return Err("--frames needs an output file to number, not stdout".to_string());
+        }
+        if parsed.checkpoint == Some(0) {
+            return Err("--checkpoint must be at least 1".to_string());
+        }
+        if parsed.resume && parsed.checkpoint.is_none() {
+            return Err("--resume needs --checkpoint".to_string());
+        }
+        if parsed.threads == Some(0) {
+            return Err("--threads must be at least 1".to_string());
+        }
+        if let Some(gamma) = parsed.gamma {
+            if !(gamma > 0.0 && gamma.is_finite()) {
+                return Err(format!("--gamma must be a positive number, got {}", gamma));
+            }
+        }
+        if let Some(exposure) = parsed.exposure {
+            if !(exposure > 0.0 && exposure.is_finite()) {
+                return Err(format!(
+                    "--exposure must be a positive number, got {}",
+                    exposure
+                ));
+            }
+        }
+        if let Some(threshold) = parsed.adaptive {
+            if threshold.is_nan() || threshold < 0.0 {
+                return Err(format!(
+                    "--adaptive must not be negative, got {}",
+                    threshold
+                ));
+            }
+        }
+        if let Some(max) = parsed.firefly_clamp {
+            if max.is_nan() || max <= 0.0 {
+                return Err(format!(
+                    "--firefly-clamp must be a positive number, got {}",
+                    max
+                ));
+            }
+        }
+        if let Some(threshold) = parsed.bloom {
+            if threshold.is_nan() || threshold < 0.0 {
+                return Err(format!("--bloom must not be negative, got {}", threshold));
+            }
+        }
+        if let Some(threshold) = parsed.edges {
+            if threshold.is_nan() || threshold < 0.0 {
+                return Err(format!("--edges must not be negative, got {}", threshold));
+            }
+        }
+        if let Some(sigma) = parsed.denoise {
+            if !(sigma > 0.0 && sigma.is_finite()) {
+                return Err(format!(
+                    "--denoise must be a positive number, got {}",
+                    sigma
+                ));
+            }
+        }
+        Ok(parsed)
+    }
+}
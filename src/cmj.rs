@@ -0,0 +1,115 @@
+// Correlated multi-jittered sampling (Kensler, "Correlated Multi-Jittered
+// Sampling", 2013). A set of `count` samples is laid out on an m x n grid with
+// one sample per cell, and their columns and rows are shuffled so that the
+// samples are also spread evenly along each axis on their own. Everything is
+// derived from hashes of the sample index and `pattern`, so a pixel gets the
+// same samples every time, in any order.
+
+// A permutation of 0..len, different for each `pattern`, computed without
+// storing it by hashing within the next power of two and skipping the values
+// that fall outside.
+fn permute(mut i: u32, len: u32, pattern: u32) -> u32 {
+    let mut mask = len - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        i ^= pattern;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= pattern >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= pattern >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= pattern >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | pattern >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < len {
+            return (i + pattern) % len;
+        }
+    }
+}
+
+// A number in [0, 1) hashed from `i` and `pattern`.
+fn random(mut i: u32, pattern: u32) -> f32 {
+    i ^= pattern;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb365_34e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc_4795);
+    i ^= 0xdf6e_307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | pattern >> 18);
+    i as f32 * (1.0 / 4_294_967_808.0)
+}
+
+// Sample `index` of a set of `count` in the unit square. Counts that are not
+// a product of two near-equal numbers leave a few cells of the grid empty.
+pub fn sample(index: u32, count: u32, pattern: u32) -> (f32, f32) {
+    let m = (count as f32).sqrt() as u32;
+    let n = count.div_ceil(m);
+    // Shuffling the order means any first few samples are spread out too,
+    // which matters when adaptive sampling stops early.
+    let s = permute(index, count, pattern.wrapping_mul(0x5163_3e2d));
+    let (column, row) = (s % m, s / m);
+    let sx = permute(column, m, pattern.wrapping_mul(0xa511_e9b3));
+    let sy = permute(row, n, pattern.wrapping_mul(0x63d8_3595));
+    let jx = random(s, pattern.wrapping_mul(0xa399_d265));
+    let jy = random(s, pattern.wrapping_mul(0x711a_d6a5));
+    (
+        ((column as f32 + (sy as f32 + jx) / n as f32) / m as f32).min(1.0 - f32::EPSILON),
+        ((row as f32 + (sx as f32 + jy) / m as f32) / n as f32).min(1.0 - f32::EPSILON),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for &len in &[1, 2, 7, 64, 100] {
+            let mut seen: Vec<u32> = (0..len).map(|i| permute(i, len, 12345)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn one_sample_per_cell_row_and_column() {
+        let (m, n) = (8, 8);
+        for &pattern in &[0, 1, 0xdead_beef] {
+            let samples: Vec<(f32, f32)> = (0..m * n).map(|i| sample(i, m * n, pattern)).collect();
+            assert_eq!(
+                samples,
+                (0..m * n)
+                    .map(|i| sample(i, m * n, pattern))
+                    .collect::<Vec<_>>()
+            );
+            let mut cells = vec![false; (m * n) as usize];
+            let mut columns = vec![false; (m * n) as usize];
+            let mut rows = vec![false; (m * n) as usize];
+            for &(u, v) in &samples {
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                let cell = (v * n as f32) as u32 * m + (u * m as f32) as u32;
+                cells[cell as usize] = true;
+                // Every one of the m * n thin columns and rows holds one.
+                columns[(u * (m * n) as f32) as usize] = true;
+                rows[(v * (m * n) as f32) as usize] = true;
+            }
+            assert!(cells.iter().chain(&columns).chain(&rows).all(|&c| c));
+        }
+    }
+}
@@ -1,17 +1,52 @@
+use std::convert::TryFrom;
 use std::fmt;
 use std::ops;
 
 use nalgebra as na;
-use serde::{Deserialize, Serialize};
 use serde_derive::*;
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+// 4x4 Bayer matrix used for ordered dithering.
+const BAYER4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Limits a channel to [0, 1], mapping NaN and infinities to 0 so that a single
+// bad sample cannot turn into a garish pixel.
+fn clamp_channel(c: f32) -> f32 {
+    if c.is_finite() {
+        c.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+// In scene files a colour is either `{"red": .., "green": .., "blue": ..}` or a
+// `"#rrggbb"` string. It is always written out in the first form, since hex
+// cannot hold values above 1 such as the brightness of a light.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "ColorRepr")]
 pub struct Color {
     red: f32,
     green: f32,
     blue: f32,
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Hex(String),
+    Channels { red: f32, green: f32, blue: f32 },
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = String;
+
+    fn try_from(repr: ColorRepr) -> Result<Color, String> {
+        match repr {
+            ColorRepr::Hex(hex) => Color::from_hex(&hex),
+            ColorRepr::Channels { red, green, blue } => Ok(Color::new(red, green, blue)),
+        }
+    }
+}
+
 impl Color {
     pub fn new(r: f32, g: f32, b: f32) -> Color {
         Color {
@@ -29,22 +64,169 @@ impl Color {
         }
     }
 
+    // The channels as a vector, in red, green, blue order.
+    pub fn to_vector(self) -> na::Vector3<f32> {
+        na::Vector3::new(self.red, self.green, self.blue)
+    }
+
+    // The channels in red, green, blue order.
+    pub fn from_array([red, green, blue]: [f32; 3]) -> Color {
+        Color { red, green, blue }
+    }
+
+    pub fn as_array(&self) -> [f32; 3] {
+        [self.red, self.green, self.blue]
+    }
+
+    pub fn r(&self) -> f32 {
+        self.red
+    }
+
+    pub fn g(&self) -> f32 {
+        self.green
+    }
+
+    pub fn b(&self) -> f32 {
+        self.blue
+    }
+
+    // Linear interpolation, giving `a` at t = 0 and `b` at t = 1.
+    pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+        (1.0 - t) * a + t * b
+    }
+
+    pub fn max_channel(&self) -> f32 {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    // Perceived brightness, weighting the channels by Rec. 709.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    // Squared Euclidean distance between the two colours as RGB vectors.
+    pub fn distance_squared(self, other: Color) -> f32 {
+        (self.red - other.red).powi(2)
+            + (self.green - other.green).powi(2)
+            + (self.blue - other.blue).powi(2)
+    }
+
     pub fn clamp(&mut self) {
-        if self.red > 1.0 {
-            self.red = 1.0
-        };
-        if self.green > 1.0 {
-            self.green = 1.0
-        };
-        if self.blue > 1.0 {
-            self.blue = 1.0
-        };
+        self.red = clamp_channel(self.red);
+        self.green = clamp_channel(self.green);
+        self.blue = clamp_channel(self.blue);
+    }
+
+    // Reinhard tone mapping, compressing [0, inf) into [0, 1) per channel.
+    pub fn tone_map_reinhard(&mut self) {
+        self.red /= 1.0 + self.red;
+        self.green /= 1.0 + self.green;
+        self.blue /= 1.0 + self.blue;
+    }
+
+    pub fn gamma_correction(&mut self, gamma: f32) {
+        let exponent = 1.0 / gamma;
+        self.red = self.red.powf(exponent);
+        self.green = self.green.powf(exponent);
+        self.blue = self.blue.powf(exponent);
+    }
+
+    // Parses `#rrggbb` into channels in [0, 1].
+    pub fn from_hex(hex: &str) -> Result<Color, String> {
+        let digits = hex
+            .strip_prefix('#')
+            .ok_or_else(|| format!("colour `{}` should start with `#`", hex))?;
+        if digits.len() != 6 {
+            return Err(format!(
+                "colour `{}` should have six hex digits after `#`",
+                hex
+            ));
+        }
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("colour `{}` contains a non-hex digit", hex));
+        }
+        let channel =
+            |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap() as f32 / u8::MAX as f32;
+        Ok(Color::new(channel(0), channel(2), channel(4)))
+    }
+
+    // `#rrggbb` with each channel clamped to [0, 1] and rounded.
+    pub fn to_hex(self) -> String {
+        let [r, g, b] = self.quantize(0.5);
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    pub fn to_rgb8(self) -> [u8; 3] {
+        self.quantize(0.0)
+    }
+
+    // Adds a sub-LSB ordered dither offset depending on the pixel position
+    // before quantizing, which breaks up banding in smooth gradients.
+    pub fn to_rgb8_dithered(self, x: u32, y: u32) -> [u8; 3] {
+        let threshold = BAYER4[(y % 4) as usize][(x % 4) as usize] as f32;
+        self.quantize((threshold + 0.5) / 16.0)
+    }
+
+    fn quantize(&self, offset: f32) -> [u8; 3] {
+        let to_u8 = |c: f32| (u8::MAX as f32 * clamp_channel(c) + offset) as u8;
+        [to_u8(self.red), to_u8(self.green), to_u8(self.blue)]
+    }
+}
+
+// A running sum of colours kept in f64, so adding hundreds of samples of
+// very different brightness doesn't drop the low-order bits of the dim ones.
+// The sum of squares is kept too, to tell how noisy the samples are.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub struct Accumulator {
+    count: u32,
+    sum: [f64; 3],
+    sum_squares: [f64; 3],
+}
+
+impl Accumulator {
+    pub fn add(&mut self, c: Color) {
+        self.count += 1;
+        for (i, &v) in [c.red, c.green, c.blue].iter().enumerate() {
+            self.sum[i] += v as f64;
+            self.sum_squares[i] += (v as f64).powi(2);
+        }
+    }
+
+    // Adds all the colours added to `other`.
+    pub fn merge(&mut self, other: &Accumulator) {
+        self.count += other.count;
+        for i in 0..3 {
+            self.sum[i] += other.sum[i];
+            self.sum_squares[i] += other.sum_squares[i];
+        }
     }
 
-    pub fn gamma_correction(&mut self) {
-        self.red = self.red.sqrt();
-        self.green = self.green.sqrt();
-        self.blue = self.blue.sqrt();
+    // The average of the added colours, or black if there are none.
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let n = self.count as f64;
+        Color::new(
+            (self.sum[0] / n) as f32,
+            (self.sum[1] / n) as f32,
+            (self.sum[2] / n) as f32,
+        )
+    }
+
+    // The largest standard error of the mean over the three channels, from
+    // the sample variance. Infinite until there are two samples.
+    pub fn standard_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.count as f64;
+        (0..3)
+            .map(|i| {
+                let variance = (self.sum_squares[i] - self.sum[i].powi(2) / n) / (n - 1.0);
+                (variance.max(0.0) / n).sqrt()
+            })
+            .fold(0.0, f64::max) as f32
     }
 }
 
@@ -67,6 +249,28 @@ impl ops::AddAssign for Color {
     }
 }
 
+impl ops::Sub for Color {
+    type Output = Self;
+    fn sub(self, _rhs: Color) -> Color {
+        Color {
+            red: self.red - _rhs.red,
+            green: self.green - _rhs.green,
+            blue: self.blue - _rhs.blue,
+        }
+    }
+}
+
+impl ops::Neg for Color {
+    type Output = Self;
+    fn neg(self) -> Color {
+        Color {
+            red: -self.red,
+            green: -self.green,
+            blue: -self.blue,
+        }
+    }
+}
+
 impl ops::Div<f32> for Color {
     type Output = Self;
     fn div(self, _num: f32) -> Color {
@@ -119,11 +323,184 @@ impl ops::Mul<Color> for f32 {
     }
 }
 
+// Parses `r,g,b`, as given on the command line. Channels must be finite and
+// not negative.
+impl std::str::FromStr for Color {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Color, ()> {
+        let channels: Vec<f32> = s
+            .split(',')
+            .map(|c| match c.trim().parse::<f32>() {
+                Ok(c) if c.is_finite() && c >= 0.0 => Ok(c),
+                _ => Err(()),
+            })
+            .collect::<Result<_, _>>()?;
+        match channels[..] {
+            [red, green, blue] => Ok(Color::new(red, green, blue)),
+            _ => Err(()),
+        }
+    }
+}
+
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let r = (u8::MAX as f32 * self.red) as u8;
-        let g = (u8::MAX as f32 * self.green) as u8;
-        let b = (u8::MAX as f32 * self.blue) as u8;
+        let [r, g, b] = self.to_rgb8();
         write!(f, "{} {} {}", r, g, b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_channels() {
+        assert_eq!("1,0.5, 2".parse(), Ok(Color::new(1.0, 0.5, 2.0)));
+        assert!("1,0.5".parse::<Color>().is_err());
+        assert!("1,0.5,2,3".parse::<Color>().is_err());
+        assert!("1,x,2".parse::<Color>().is_err());
+        assert!("1,-0.5,2".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn channels() {
+        let c = Color::new(0.5, 0.25, 2.0);
+        assert_eq!((c.r(), c.g(), c.b()), (0.5, 0.25, 2.0));
+        assert_eq!(c.as_array(), [0.5, 0.25, 2.0]);
+        assert_eq!(Color::from_array(c.as_array()), c);
+        assert_eq!(c.as_array().iter().sum::<f32>(), 2.75);
+    }
+
+    #[test]
+    fn lerp_ends_and_middle() {
+        let a = Color::new(0.0, 0.5, 2.0);
+        let b = Color::new(1.0, 0.5, 0.0);
+        assert_eq!(Color::lerp(a, b, 0.0), a);
+        assert_eq!(Color::lerp(a, b, 1.0), b);
+        assert_eq!(Color::lerp(a, b, 0.5), Color::new(0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn hex_errors_and_round_trip() {
+        assert_eq!(
+            Color::from_hex("#ff8000"),
+            Ok(Color::new(1.0, 128.0 / 255.0, 0.0))
+        );
+        assert!(Color::from_hex("ff8000")
+            .unwrap_err()
+            .contains("start with `#`"));
+        for short_or_long in ["#fff", "#ff80001", "#"] {
+            assert!(Color::from_hex(short_or_long)
+                .unwrap_err()
+                .contains("six hex digits"));
+        }
+        assert!(Color::from_hex("#ff80zz")
+            .unwrap_err()
+            .contains("non-hex digit"));
+        for hex in ["#000000", "#ffffff", "#1a2b3c", "#80ff01"] {
+            assert_eq!(Color::from_hex(hex).unwrap().to_hex(), hex);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Color::new(0.5, 0.25, 1.0);
+        let b = Color::new(0.25, 0.5, 2.0);
+        assert_eq!(a + b, Color::new(0.75, 0.75, 3.0));
+        assert_eq!(a - b, Color::new(0.25, -0.25, -1.0));
+        assert_eq!(a * b, Color::new(0.125, 0.125, 2.0));
+        assert_eq!(a * 2.0, Color::new(1.0, 0.5, 2.0));
+        assert_eq!(2.0 * a, a * 2.0);
+        assert_eq!(b / 2.0, Color::new(0.125, 0.25, 1.0));
+        assert_eq!(-a, Color::new(-0.5, -0.25, -1.0));
+        assert_eq!(a - a, Color::new(0.0, 0.0, 0.0));
+        assert_eq!(-(-a), a);
+
+        let mut c = a;
+        c += b;
+        assert_eq!(c, a + b);
+        c *= b;
+        assert_eq!(c, (a + b) * b);
+    }
+
+    #[test]
+    fn luminance() {
+        assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < 1e-6);
+        assert_eq!(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152);
+        assert_eq!(Color::new(0.0, 0.0, 0.0).luminance(), 0.0);
+        assert!(Color::new(0.0, 0.0, 1.0).luminance() < Color::new(1.0, 0.0, 0.0).luminance());
+    }
+
+    #[test]
+    fn accumulator_keeps_small_samples() {
+        // Summed in f32 the ones would vanish: 1e8 + 1 rounds back to 1e8.
+        let mut sum = Accumulator::default();
+        sum.add(Color::new(1e8, 0.0, 0.0));
+        for _ in 0..8 {
+            sum.add(Color::new(1.0, 1.0, 0.0));
+        }
+        sum.add(Color::new(-1e8, 0.0, 0.0));
+        assert_eq!(sum.mean(), Color::new(0.8, 0.8, 0.0));
+    }
+
+    #[test]
+    fn accumulator_standard_error() {
+        let mut sum = Accumulator::default();
+        assert_eq!(sum.mean(), Color::new(0.0, 0.0, 0.0));
+        sum.add(Color::new(0.5, 0.5, 0.5));
+        assert_eq!(sum.standard_error(), f32::INFINITY);
+        sum.add(Color::new(0.5, 0.5, 0.5));
+        assert_eq!(sum.standard_error(), 0.0);
+        // Samples 0, 1, 0, 1 have variance 1/3, so the error is sqrt(1/12).
+        let mut sum = Accumulator::default();
+        for i in 0..4 {
+            sum.add(Color::new(0.0, (i % 2) as f32, 0.0));
+        }
+        assert!((sum.standard_error() - (1.0f32 / 12.0).sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn merged_accumulators_match_one() {
+        let colors = [0.1, 0.7, 0.2, 0.9, 0.4].map(|c| Color::new(c, 1.0 - c, 0.5));
+        let mut all = Accumulator::default();
+        let (mut first, mut second) = (Accumulator::default(), Accumulator::default());
+        for (i, &c) in colors.iter().enumerate() {
+            all.add(c);
+            if i < 2 {
+                first.add(c);
+            } else {
+                second.add(c);
+            }
+        }
+        first.merge(&second);
+        assert!(first.mean().distance_squared(all.mean()) < 1e-12);
+        assert!((first.standard_error() - all.standard_error()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clamp_handles_out_of_range_and_non_finite() {
+        // Negative, above one, NaN, and both infinities.
+        let cases = [
+            (-0.5, 0.0, 0),
+            (1.5, 1.0, 255),
+            (f32::NAN, 0.0, 0),
+            (f32::INFINITY, 0.0, 0),
+            (f32::NEG_INFINITY, 0.0, 0),
+        ];
+        for &(channel, clamped, byte) in &cases {
+            let mut c = Color::new(channel, channel, channel);
+            assert_eq!(c.to_rgb8(), [byte; 3], "{}", channel);
+            c.clamp();
+            assert_eq!(c, Color::new(clamped, clamped, clamped), "{}", channel);
+        }
+    }
+
+    #[test]
+    fn display_writes_bytes() {
+        assert_eq!(Color::new(0.0, 0.5, 1.0).to_string(), "0 127 255");
+        // Out-of-range and non-finite channels are clamped first.
+        assert_eq!(Color::new(-1.0, 2.0, f32::NAN).to_string(), "0 255 0");
+        assert_eq!(Color::new(1.0, 0.0, 0.0).to_rgb8(), [255, 0, 0]);
+    }
+}
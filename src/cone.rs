@@ -0,0 +1,215 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{orthonormal_basis, HitRecord, Material, Object};
+
+// A closed cone with its tip at `apex`, opening along `axis` at `half_angle`
+// degrees from it for `height`, where a flat cap closes it. The axis does not
+// need to be normalized.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Cone {
+    pub apex: na::Point3<f32>,
+    pub axis: na::Vector3<f32>,
+    pub half_angle: f32,
+    pub height: f32,
+    pub material: Material,
+}
+
+impl Cone {
+    fn base_radius(&self) -> f32 {
+        self.height * self.half_angle.to_radians().tan()
+    }
+
+    // The angle around the axis of `radial`, a vector perpendicular to it, as a
+    // fraction of a turn.
+    fn turn(axis: na::Vector3<f32>, radial: na::Vector3<f32>) -> f32 {
+        let (u, v) = orthonormal_basis(axis);
+        let phi = radial.dot(&v).atan2(radial.dot(&u)) + std::f32::consts::PI;
+        phi / (2.0 * std::f32::consts::PI)
+    }
+
+    // Nearest hit on the sloping side. uv is the angle around the axis and
+    // the fraction of the height.
+    fn side(
+        &self,
+        ray: &ray::Ray,
+        axis: na::Vector3<f32>,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<HitRecord> {
+        // Points p on the double cone through the apex satisfy
+        // ((p - apex) . axis)^2 = cos^2 * |p - apex|^2.
+        let cos2 = self.half_angle.to_radians().cos().powi(2);
+        let co = ray.orig - self.apex;
+        let d_axis = ray.direction.dot(&axis);
+        let co_axis = co.dot(&axis);
+        let a = d_axis.powi(2) - cos2 * ray.direction.norm_squared();
+        let half_b = d_axis * co_axis - cos2 * ray.direction.dot(&co);
+        let c = co_axis.powi(2) - cos2 * co.norm_squared();
+
+        let roots = if a.abs() < 1e-8 {
+            // A ray parallel to the side crosses the double cone just once.
+            if half_b.abs() < 1e-8 {
+                return None;
+            }
+            [-c / (2.0 * half_b), f32::NAN]
+        } else {
+            let discriminant = half_b.powi(2) - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            // Nearly parallel rays make `a` tiny, so the root that stays
+            // finite is found without dividing by it.
+            let q = -(half_b + discriminant.sqrt().copysign(half_b));
+            let (t0, t1) = (q / a, c / q);
+            [t0.min(t1), t0.max(t1)]
+        };
+        roots
+            .iter()
+            .copied()
+            .filter(|t| *t >= t_min && *t <= t_max)
+            .find_map(|t| {
+                let v = ray.at(t) - self.apex;
+                let h = v.dot(&axis);
+                // The other nappe of the double cone, or beyond the cap.
+                if h < 0.0 || h > self.height {
+                    return None;
+                }
+                // Away from the axis and back towards the apex; at the apex
+                // itself there is no normal, so point straight back.
+                let normal = cos2 * v - h * axis;
+                let outward = if normal.norm_squared() > 1e-12 {
+                    normal.normalize()
+                } else {
+                    -axis
+                };
+                Some(HitRecord::new(
+                    ray,
+                    t,
+                    outward,
+                    (Cone::turn(axis, v - h * axis), h / self.height),
+                    self.material,
+                ))
+            })
+    }
+
+    // Hit on the cap closing the cone. uv is the angle around the axis and
+    // the fraction of the radius.
+    fn cap(
+        &self,
+        ray: &ray::Ray,
+        axis: na::Vector3<f32>,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<HitRecord> {
+        let denom = ray.direction.dot(&axis);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let centre = self.apex + self.height * axis;
+        let t = (centre - ray.orig).dot(&axis) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let radial = ray.at(t) - centre;
+        let radius = self.base_radius();
+        if radial.norm_squared() > radius.powi(2) {
+            return None;
+        }
+        Some(HitRecord::new(
+            ray,
+            t,
+            axis,
+            (Cone::turn(axis, radial), radial.norm() / radius),
+            self.material,
+        ))
+    }
+}
+
+impl Object for Cone {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let axis = self.axis.normalize();
+        let side = self.side(ray, axis, t_min, t_max);
+        let t_max = side.map_or(t_max, |hit| hit.t);
+        self.cap(ray, axis, t_min, t_max).or(side)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let axis = self.axis.normalize();
+        let centre = self.apex + self.height * axis;
+        // How far the rim reaches out from the axis along each world axis.
+        let radius = self.base_radius();
+        let extent = axis.map(|a| radius * (1.0 - a * a).max(0.0).sqrt());
+        Some(aabb::Aabb::new(
+            self.apex.inf(&(centre - extent)),
+            self.apex.sup(&(centre + extent)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{grey, ray};
+    use crate::T_MIN;
+
+    // Tip at the origin, opening upwards at 45 degrees to a cap at y = 1.
+    fn cone() -> Cone {
+        Cone {
+            apex: na::Point3::origin(),
+            axis: na::Vector3::new(0.0, 3.0, 0.0),
+            half_angle: 45.0,
+            height: 1.0,
+            material: grey(),
+        }
+    }
+
+    #[test]
+    fn side_hit_and_normal() {
+        let hit = cone()
+            .intersect(
+                &ray([2.0, 0.5, 0.0], [-1.0, 0.0, 0.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        let outward = na::Vector3::new(1.0, -1.0, 0.0).normalize();
+        assert!((hit.normal - outward).norm() < 1e-5);
+        assert!(hit.front_face);
+        assert!((hit.uv.1 - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cap_and_other_nappe() {
+        let down = ray([0.2, 3.0, 0.0], [0.0, -1.0, 0.0]);
+        let hit = cone().intersect(&down, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!((hit.normal - na::Vector3::y()).norm() < 1e-5);
+        // Below the apex, where the mirror-image cone would be.
+        let below = ray([2.0, -0.5, 0.0], [-1.0, 0.0, 0.0]);
+        assert!(cone().intersect(&below, T_MIN, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn parallel_and_apex_rays_stay_finite() {
+        // Parallel to the far side of the cone, entering through the near one.
+        let parallel = ray([-1.0, 0.0, 0.0], [1.0, 1.0, 0.0]);
+        let hit = cone().intersect(&parallel, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-4);
+        assert!(hit.normal.iter().all(|n| n.is_finite()));
+        // Straight at the tip along the axis, which grazes it at best.
+        let tip = ray([0.0, -1.0, 0.0], [0.0, 1.0, 0.0]);
+        let hit = cone().intersect(&tip, T_MIN, f32::INFINITY).unwrap();
+        assert!(hit.t.is_finite() && hit.normal.iter().all(|n| n.is_finite()));
+    }
+
+    #[test]
+    fn bounding_box_holds_apex_and_rim() {
+        let bbox = cone().bounding_box().unwrap();
+        assert!((bbox.min - na::Point3::new(-1.0, 0.0, -1.0)).norm() < 1e-5);
+        assert!((bbox.max - na::Point3::new(1.0, 1.0, 1.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,126 @@
+use nalgebra as na;
+
+use crate::aabb;
+use crate::ray;
+use crate::rect::{XYRect, XZRect, YZRect};
+use crate::{HitRecord, Material, Object};
+
+// An axis-aligned box made of six rectangles. The first rectangle of each pair
+// lies on the `min` side of the box.
+#[derive(Clone, Copy)]
+pub struct Cube {
+    min: na::Point3<f32>,
+    max: na::Point3<f32>,
+    xy: [XYRect; 2],
+    xz: [XZRect; 2],
+    yz: [YZRect; 2],
+}
+
+impl Cube {
+    // `p0` and `p1` are any two opposite corners.
+    pub fn new(p0: na::Point3<f32>, p1: na::Point3<f32>, material: Material) -> Cube {
+        let min = p0.inf(&p1);
+        let max = p0.sup(&p1);
+        let xy = |k, flip| XYRect {
+            x0: min[0],
+            x1: max[0],
+            y0: min[1],
+            y1: max[1],
+            k,
+            flip,
+            material,
+        };
+        let xz = |k, flip| XZRect {
+            x0: min[0],
+            x1: max[0],
+            z0: min[2],
+            z1: max[2],
+            k,
+            flip,
+            material,
+        };
+        let yz = |k, flip| YZRect {
+            y0: min[1],
+            y1: max[1],
+            z0: min[2],
+            z1: max[2],
+            k,
+            flip,
+            material,
+        };
+        Cube {
+            min,
+            max,
+            // The faces on the min side are flipped so every face points out
+            // of the box.
+            xy: [xy(min[2], true), xy(max[2], false)],
+            xz: [xz(min[1], true), xz(max[1], false)],
+            yz: [yz(min[0], true), yz(max[0], false)],
+        }
+    }
+}
+
+impl Object for Cube {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let faces: [&dyn Object; 6] = [
+            &self.xy[0],
+            &self.xy[1],
+            &self.xz[0],
+            &self.xz[1],
+            &self.yz[0],
+            &self.yz[1],
+        ];
+        let mut closest = None;
+        let mut t_max = t_max;
+        for face in faces {
+            if let Some(hit) = face.intersect(ray, t_min, t_max) {
+                t_max = hit.t;
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        Some(aabb::Aabb::new(self.min, self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{grey, ray};
+    use crate::T_MIN;
+
+    fn cube() -> Cube {
+        Cube::new(
+            na::Point3::new(1.0, 1.0, 1.0),
+            na::Point3::new(-1.0, -1.0, -1.0),
+            grey(),
+        )
+    }
+
+    #[test]
+    fn ray_from_inside_hits_far_face() {
+        let r = ray([0.0, 0.5, 0.0], [1.0, 0.0, 0.0]);
+        let hit = cube().intersect(&r, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!((hit.point - na::Point3::new(1.0, 0.5, 0.0)).norm() < 1e-5);
+        // The face points out of the box, so from inside it is the back.
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, -na::Vector3::x());
+    }
+
+    #[test]
+    fn nearest_face_from_outside_and_miss() {
+        let r = ray([0.2, 0.3, 5.0], [0.0, 0.0, -1.0]);
+        let hit = cube().intersect(&r, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, na::Vector3::z());
+        let past = ray([1.5, 0.0, 5.0], [0.0, 0.0, -1.0]);
+        assert!(cube().intersect(&past, T_MIN, f32::INFINITY).is_none());
+        let away = ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0]);
+        assert!(cube().intersect(&away, T_MIN, f32::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{orthonormal_basis, HitRecord, Material, Object};
+
+// A closed cylinder of `radius` whose axis runs from `base` for `height` along
+// `axis`. The axis does not need to be normalized.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Cylinder {
+    pub base: na::Point3<f32>,
+    pub axis: na::Vector3<f32>,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Material,
+}
+
+impl Cylinder {
+    // The angle around the axis as a fraction of a turn, and the distance from
+    // the axis as a fraction of the radius.
+    fn polar(&self, axis: na::Vector3<f32>, radial: na::Vector3<f32>) -> (f32, f32) {
+        let (u, v) = orthonormal_basis(axis);
+        let phi = radial.dot(&v).atan2(radial.dot(&u)) + std::f32::consts::PI;
+        (
+            phi / (2.0 * std::f32::consts::PI),
+            radial.norm() / self.radius,
+        )
+    }
+
+    // Nearest hit on the curved side. uv is the angle around the axis and the
+    // fraction of the height.
+    fn side(
+        &self,
+        ray: &ray::Ray,
+        axis: na::Vector3<f32>,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<HitRecord> {
+        let oc = ray.orig - self.base;
+        let d_perp = ray.direction - ray.direction.dot(&axis) * axis;
+        let oc_perp = oc - oc.dot(&axis) * axis;
+        let a = d_perp.norm_squared();
+        // A ray parallel to the axis only ever meets the caps.
+        if a < 1e-8 {
+            return None;
+        }
+        let half_b = d_perp.dot(&oc_perp);
+        let c = oc_perp.norm_squared() - self.radius.powi(2);
+        let discriminant = half_b.powi(2) - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt_d = discriminant.sqrt();
+        [(-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a]
+            .iter()
+            .copied()
+            .filter(|t| *t >= t_min && *t <= t_max)
+            .find_map(|t| {
+                let h = (ray.at(t) - self.base).dot(&axis);
+                if h < 0.0 || h > self.height {
+                    return None;
+                }
+                let radial = oc_perp + t * d_perp;
+                let (u, _) = self.polar(axis, radial);
+                Some(HitRecord::new(
+                    ray,
+                    t,
+                    radial / self.radius,
+                    (u, h / self.height),
+                    self.material,
+                ))
+            })
+    }
+
+    // Hit on the flat cap at height `h` facing along `normal`. uv is the angle
+    // around the axis and the fraction of the radius.
+    fn cap(
+        &self,
+        ray: &ray::Ray,
+        axis: na::Vector3<f32>,
+        h: f32,
+        normal: na::Vector3<f32>,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<HitRecord> {
+        let denom = ray.direction.dot(&axis);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let centre = self.base + h * axis;
+        let t = (centre - ray.orig).dot(&axis) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let radial = ray.at(t) - centre;
+        if radial.norm_squared() > self.radius.powi(2) {
+            return None;
+        }
+        Some(HitRecord::new(
+            ray,
+            t,
+            normal,
+            self.polar(axis, radial),
+            self.material,
+        ))
+    }
+}
+
+impl Object for Cylinder {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let axis = self.axis.normalize();
+        let mut closest = self.side(ray, axis, t_min, t_max);
+        for &(h, normal) in &[(0.0, -axis), (self.height, axis)] {
+            let t_max = closest.map_or(t_max, |hit| hit.t);
+            if let Some(hit) = self.cap(ray, axis, h, normal, t_min, t_max) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let axis = self.axis.normalize();
+        let top = self.base + self.height * axis;
+        // How far the rim reaches out from the axis along each world axis.
+        let extent = axis.map(|a| self.radius * (1.0 - a * a).max(0.0).sqrt());
+        Some(aabb::Aabb::new(
+            self.base.inf(&top) - extent,
+            self.base.sup(&top) + extent,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{grey, ray};
+    use crate::T_MIN;
+
+    // Radius 1 around the y axis, from y = 0 up to y = 2.
+    fn cylinder() -> Cylinder {
+        Cylinder {
+            base: na::Point3::origin(),
+            axis: na::Vector3::new(0.0, 3.0, 0.0),
+            radius: 1.0,
+            height: 2.0,
+            material: grey(),
+        }
+    }
+
+    #[test]
+    fn side_hit_and_normal() {
+        let hit = cylinder()
+            .intersect(
+                &ray([3.0, 0.5, 0.0], [-1.0, 0.0, 0.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!((hit.normal - na::Vector3::x()).norm() < 1e-5);
+        assert!(hit.front_face);
+        assert!((hit.uv.1 - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn caps_close_both_ends() {
+        let down = ray([0.5, 5.0, 0.0], [0.0, -1.0, 0.0]);
+        let hit = cylinder().intersect(&down, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert!((hit.normal - na::Vector3::y()).norm() < 1e-5);
+        // From inside, the bottom cap is the far side.
+        let inside = ray([0.5, 1.0, 0.0], [0.0, -1.0, 0.0]);
+        let hit = cylinder().intersect(&inside, T_MIN, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn misses_beside_and_beyond_the_ends() {
+        let beside = ray([3.0, 0.5, 1.5], [-1.0, 0.0, 0.0]);
+        assert!(cylinder()
+            .intersect(&beside, T_MIN, f32::INFINITY)
+            .is_none());
+        let above = ray([3.0, 2.5, 0.0], [-1.0, 0.0, 0.0]);
+        assert!(cylinder().intersect(&above, T_MIN, f32::INFINITY).is_none());
+        // Parallel to the axis, but outside the radius.
+        let past = ray([1.5, 5.0, 0.0], [0.0, -1.0, 0.0]);
+        assert!(cylinder().intersect(&past, T_MIN, f32::INFINITY).is_none());
+    }
+}
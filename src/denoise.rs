@@ -0,0 +1,74 @@
+use crate::color::Color;
+
+// Edge-preserving smoothing: each pixel becomes a weighted average of its
+// neighbours up to `radius` pixels away, with weights falling off both with
+// distance and with how far a neighbour's colour is from the pixel's own. A
+// `sigma` of about 0.1 flattens sampling noise while keeping edges between
+// differently coloured surfaces.
+#[derive(Clone, Copy)]
+pub struct Denoise {
+    pub radius: u32,
+    pub sigma: f32,
+}
+
+impl Denoise {
+    pub fn apply(&self, pixels: &mut [Color], width: u32, height: u32) {
+        let (w, h) = (width as i64, height as i64);
+        let r = self.radius as i64;
+        // As in `Bloom`, the window edge sits at a few standard deviations.
+        let spatial = (self.radius as f32 / 2.0).max(0.5);
+
+        let source = pixels.to_vec();
+        for y in 0..h {
+            for x in 0..w {
+                let centre = source[(y * w + x) as usize];
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for ny in (y - r).max(0)..=(y + r).min(h - 1) {
+                    for nx in (x - r).max(0)..=(x + r).min(w - 1) {
+                        let neighbour = source[(ny * w + nx) as usize];
+                        let distance2 = ((nx - x).pow(2) + (ny - y).pow(2)) as f32;
+                        let weight = (-distance2 / (2.0 * spatial * spatial)
+                            - neighbour.distance_squared(centre) / (2.0 * self.sigma * self.sigma))
+                            .exp();
+                        // Skipping zero weights also keeps an infinite
+                        // neighbour from turning the sum into NaN.
+                        if weight > 0.0 {
+                            sum += weight * neighbour;
+                            total += weight;
+                        }
+                    }
+                }
+                // A finite centre pixel has weight 1, but a NaN or infinite
+                // one is at no finite distance from anything, itself
+                // included, and is left for clamping to deal with.
+                if total > 0.0 {
+                    pixels[(y * w + x) as usize] = sum / total;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_pixels_stay_put() {
+        let grey = Color::new(0.5, 0.5, 0.5);
+        for bad in [f32::NAN, f32::INFINITY] {
+            let mut pixels = vec![grey, Color::new(bad, bad, bad), grey];
+            Denoise {
+                radius: 1,
+                sigma: 0.1,
+            }
+            .apply(&mut pixels, 3, 1);
+            assert_eq!(pixels[0], grey);
+            assert_eq!(pixels[2], grey);
+            // Left as it was rather than turned into 0 / 0.
+            let kept = pixels[1].r();
+            assert!(kept.is_nan() == bad.is_nan() && (bad.is_nan() || kept == bad));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use nalgebra as na;
+use rand::Rng;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{orthonormal_basis, HitRecord, Material, Object, Sampleable, T_MIN};
+
+// A flat disk of `radius` around `centre`, facing along `normal`.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Disk {
+    pub centre: na::Point3<f32>,
+    pub normal: na::Vector3<f32>,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Disk {
+    fn area(&self) -> f32 {
+        std::f32::consts::PI * self.radius.powi(2)
+    }
+}
+
+impl Object for Disk {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let normal = self.normal.normalize();
+        let denom = normal.dot(&ray.direction);
+        // A ray parallel to the disk never hits it.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.centre - ray.orig).dot(&normal) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let offset = ray.at(t) - self.centre;
+        let distance = offset.norm();
+        if distance > self.radius {
+            return None;
+        }
+        // u goes once round the disk and v runs from the centre to the rim.
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let angle = offset.dot(&bitangent).atan2(offset.dot(&tangent)) + std::f32::consts::PI;
+        Some(HitRecord::new(
+            ray,
+            t,
+            normal,
+            (angle / (2.0 * std::f32::consts::PI), distance / self.radius),
+            self.material,
+        ))
+    }
+
+    // Along each axis the rim reaches out by the radius times the sine of the
+    // angle between that axis and the normal.
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let normal = self.normal.normalize();
+        let extent = normal.map(|n| self.radius * (1.0 - n * n).max(0.0).sqrt());
+        Some(aabb::Aabb::new(self.centre - extent, self.centre + extent))
+    }
+}
+
+impl Sampleable for Disk {
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        let (tangent, bitangent) = orthonormal_basis(self.normal.normalize());
+        // The square root spreads points evenly over the area.
+        let r = self.radius * rng.gen::<f32>().sqrt();
+        let angle = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+        let pt = self.centre + r * (angle.cos() * tangent + angle.sin() * bitangent);
+        pt - origin
+    }
+
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        let ray = ray::Ray::new(origin, direction, 0.0);
+        match self.intersect(&ray, T_MIN, f32::INFINITY) {
+            Some(hit) => {
+                let cosine = ray.direction.dot(&hit.normal).abs();
+                hit.t.powi(2) / (cosine * self.area())
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{grey, ray};
+
+    fn disk() -> Disk {
+        Disk {
+            centre: na::Point3::new(0.0, 1.0, 0.0),
+            normal: na::Vector3::new(0.0, 2.0, 0.0),
+            radius: 0.5,
+            material: grey(),
+        }
+    }
+
+    #[test]
+    fn hit_inside_radius() {
+        let hit = disk()
+            .intersect(&ray([0.3, 0.0, 0.0], [0.0, 1.0, 0.0]), T_MIN, f32::INFINITY)
+            .unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!((hit.normal - na::Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-5);
+        assert!(!hit.front_face);
+        assert!((hit.uv.1 - 0.6).abs() < 1e-5);
+    }
+
+    #[test]
+    fn miss_outside_radius() {
+        let r = ray([0.6, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert!(disk().intersect(&r, T_MIN, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn miss_parallel_and_behind() {
+        let parallel = ray([-2.0, 1.0, 0.0], [1.0, 0.0, 0.0]);
+        assert!(disk().intersect(&parallel, T_MIN, f32::INFINITY).is_none());
+        let away = ray([0.0, 0.0, 0.0], [0.0, -1.0, 0.0]);
+        assert!(disk().intersect(&away, T_MIN, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bounding_box_is_flat_along_normal() {
+        let bbox = disk().bounding_box().unwrap();
+        assert!((bbox.min - na::Point3::new(-0.5, 1.0, -0.5)).norm() < 1e-6);
+        assert!((bbox.max - na::Point3::new(0.5, 1.0, 0.5)).norm() < 1e-6);
+    }
+}
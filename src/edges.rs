@@ -0,0 +1,65 @@
+use crate::color::Color;
+
+// A second pass for anti-aliasing: after every pixel has had the usual
+// samples, those whose colour differs from a neighbour's by more than
+// `threshold` are sampled again up to `samples`, which concentrates the work
+// on silhouettes and other sharp edges.
+#[derive(Clone, Copy)]
+pub struct Edges {
+    pub threshold: f32,
+    pub samples: u32,
+}
+
+impl Edges {
+    // Whether each pixel lies on an edge, by the largest distance between its
+    // colour and that of any of its eight neighbours. Colours are clamped to
+    // what the image can show first, so the inside of a light, brighter
+    // than white, doesn't count as high contrast.
+    pub fn detect(&self, pixels: &[Color], width: u32, height: u32) -> Vec<bool> {
+        let (w, h) = (width as i64, height as i64);
+        let shown: Vec<Color> = pixels
+            .iter()
+            .map(|&c| {
+                let mut c = c;
+                c.clamp();
+                c
+            })
+            .collect();
+        let threshold2 = self.threshold * self.threshold;
+        (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let centre = shown[(y * w + x) as usize];
+                ((y - 1).max(0)..=(y + 1).min(h - 1)).any(|ny| {
+                    ((x - 1).max(0)..=(x + 1).min(w - 1)).any(|nx| {
+                        shown[(ny * w + nx) as usize].distance_squared(centre) > threshold2
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_both_sides_of_an_edge() {
+        // A 4x3 image, dark on the left half and white on the right, with a
+        // light far brighter than white in one corner.
+        let dark = Color::new(0.1, 0.1, 0.1);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut pixels = vec![dark, dark, white, white];
+        pixels = pixels.repeat(3);
+        pixels[3] = Color::new(20.0, 20.0, 20.0);
+        let edges = Edges {
+            threshold: 0.5,
+            samples: 64,
+        };
+        let flagged = edges.detect(&pixels, 4, 3);
+        for row in flagged.chunks(4) {
+            assert_eq!(row, [false, true, true, false]);
+        }
+    }
+}
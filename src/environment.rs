@@ -0,0 +1,65 @@
+use std::convert::TryFrom;
+
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::color;
+use crate::image_texture;
+
+// An equirectangular (latitude/longitude) image surrounding the scene. In scene
+// files it is given by the path of any image the `image` crate can read; HDR
+// and EXR images are used as linear radiance.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EnvironmentMap {
+    path: String,
+    width: u32,
+    height: u32,
+    pixels: Vec<color::Color>,
+}
+
+impl EnvironmentMap {
+    pub fn load(path: &str) -> image::ImageResult<EnvironmentMap> {
+        let image = image::open(path)?.into_rgb32f();
+        Ok(EnvironmentMap {
+            path: path.to_string(),
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|p| color::Color::new(p[0], p[1], p[2]))
+                .collect(),
+        })
+    }
+
+    // The radiance arriving from `direction`.
+    pub fn value(
+        &self,
+        direction: na::Vector3<f32>,
+        filter: image_texture::Filter,
+    ) -> color::Color {
+        let d = direction.normalize();
+        let theta = (-d[1]).clamp(-1.0, 1.0).acos();
+        let phi = (-d[2]).atan2(d[0]) + std::f32::consts::PI;
+        let u = phi / (2.0 * std::f32::consts::PI);
+        let v = theta / std::f32::consts::PI;
+
+        // Longitude wraps around, but the rows stop at the poles.
+        image_texture::sample(&self.pixels, self.width, self.height, (u, v), false, filter)
+    }
+}
+
+impl TryFrom<String> for EnvironmentMap {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<EnvironmentMap, String> {
+        EnvironmentMap::load(&path)
+            .map_err(|e| format!("cannot load environment map {}: {}", path, e))
+    }
+}
+
+impl From<EnvironmentMap> for String {
+    fn from(map: EnvironmentMap) -> String {
+        map.path
+    }
+}
@@ -0,0 +1,172 @@
+use std::convert::TryFrom;
+use std::io;
+use std::sync::Mutex;
+
+use serde_derive::*;
+
+use crate::color;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    // Interpolates between the four texels around the lookup.
+    Bilinear,
+}
+
+// Looks up `pixels`, a `width` x `height` image stored top row first, at
+// (u, v) in [0, 1], with v running bottom to top. Bilinear filtering wraps
+// around from the right edge to the left one, and from the bottom to the top
+// if `wrap_rows` is set; otherwise it stops at the top and bottom rows.
+pub fn sample(
+    pixels: &[color::Color],
+    width: u32,
+    height: u32,
+    (u, v): (f32, f32),
+    wrap_rows: bool,
+    filter: Filter,
+) -> color::Color {
+    let x = u * width as f32;
+    let y = (1.0 - v) * height as f32;
+    if filter == Filter::Nearest {
+        let x = (x as u32).min(width - 1);
+        let y = (y as u32).min(height - 1);
+        return pixels[(y * width + x) as usize];
+    }
+
+    // Texel centres sit at half-integer coordinates.
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let (w, h) = (width as i64, height as i64);
+    let texel = |i: i64, j: i64| {
+        let row = if wrap_rows {
+            j.rem_euclid(h)
+        } else {
+            j.clamp(0, h - 1)
+        };
+        pixels[(row * w + i.rem_euclid(w)) as usize]
+    };
+    let (i, j) = (x0 as i64, y0 as i64);
+    color::Color::lerp(
+        color::Color::lerp(texel(i, j), texel(i + 1, j), tx),
+        color::Color::lerp(texel(i, j + 1), texel(i + 1, j + 1), tx),
+        ty,
+    )
+}
+
+// An image wrapped around a surface by its (u, v) coordinates. Textures are
+// `Copy`, so a loaded image is kept for the rest of the run and shared through
+// a `&'static` reference. In scene files it is given by the image's path.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ImageTexture(&'static ImageData);
+
+pub struct ImageData {
+    path: String,
+    width: u32,
+    height: u32,
+    pixels: Vec<color::Color>,
+}
+
+// Every image loaded so far, so that materials naming the same file share
+// one copy of it instead of each keeping their own.
+static LOADED: Mutex<Vec<&'static ImageData>> = Mutex::new(Vec::new());
+
+impl ImageTexture {
+    pub fn load(path: &str) -> io::Result<ImageTexture> {
+        let mut loaded = LOADED.lock().unwrap();
+        if let Some(&data) = loaded.iter().find(|data| data.path == path) {
+            return Ok(ImageTexture(data));
+        }
+        let image = image::open(path)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("cannot load image texture {}: {}", path, e),
+                )
+            })?
+            .into_rgb32f();
+        let data = ImageData {
+            path: path.to_string(),
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|p| color::Color::new(p[0], p[1], p[2]))
+                .collect(),
+        };
+        let data = Box::leak(Box::new(data));
+        loaded.push(data);
+        Ok(ImageTexture(data))
+    }
+
+    // u runs left to right and v bottom to top, and both wrap around outside
+    // [0, 1] so the image tiles.
+    pub fn value(&self, uv: (f32, f32), filter: Filter) -> color::Color {
+        let image = self.0;
+        let uv = (uv.0.rem_euclid(1.0), uv.1.rem_euclid(1.0));
+        sample(&image.pixels, image.width, image.height, uv, true, filter)
+    }
+}
+
+impl TryFrom<String> for ImageTexture {
+    type Error = io::Error;
+
+    fn try_from(path: String) -> io::Result<ImageTexture> {
+        ImageTexture::load(&path)
+    }
+}
+
+impl From<ImageTexture> for String {
+    fn from(texture: ImageTexture) -> String {
+        texture.0.path.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(c: f32) -> color::Color {
+        color::Color::new(c, c, c)
+    }
+
+    // A 2 x 2 image: black and white on top, then white and black.
+    fn checker() -> Vec<color::Color> {
+        vec![grey(0.0), grey(1.0), grey(1.0), grey(0.0)]
+    }
+
+    #[test]
+    fn same_path_loads_once() {
+        let first = ImageTexture::load("textures/bumps_normal.png").unwrap();
+        let second = ImageTexture::load("textures/bumps_normal.png").unwrap();
+        assert!(std::ptr::eq(first.0, second.0));
+    }
+
+    #[test]
+    fn bilinear_blends_neighbouring_texels() {
+        let pixels = checker();
+        // The centre of the top-left texel, then halfway to its right neighbour.
+        let top_left = sample(&pixels, 2, 2, (0.25, 0.75), false, Filter::Bilinear);
+        assert_eq!(top_left, grey(0.0));
+        let between = sample(&pixels, 2, 2, (0.5, 0.75), false, Filter::Bilinear);
+        assert_eq!(between, grey(0.5));
+        let blocky = sample(&pixels, 2, 2, (0.5, 0.75), false, Filter::Nearest);
+        assert_eq!(blocky, grey(1.0));
+    }
+
+    #[test]
+    fn bilinear_wraps_across_u_seam() {
+        let pixels = checker();
+        // Both edges of the top row lie halfway between its two texels.
+        for &u in &[0.0, 1.0] {
+            let c = sample(&pixels, 2, 2, (u, 0.75), false, Filter::Bilinear);
+            assert_eq!(c, grey(0.5));
+        }
+        // Rows are clamped unless asked to wrap too.
+        let top = sample(&pixels, 2, 2, (0.25, 1.0), false, Filter::Bilinear);
+        assert_eq!(top, grey(0.0));
+        let wrapped = sample(&pixels, 2, 2, (0.25, 1.0), true, Filter::Bilinear);
+        assert_eq!(wrapped, grey(0.5));
+    }
+}
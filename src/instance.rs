@@ -0,0 +1,170 @@
+use nalgebra as na;
+
+use crate::aabb;
+use crate::ray;
+use crate::{HitRecord, Object};
+
+// Moves `object` by `offset`. The ray is moved the other way instead, so the
+// wrapped object never needs to know.
+pub struct Translate {
+    pub object: Box<dyn Object + Sync>,
+    pub offset: na::Vector3<f32>,
+}
+
+impl Object for Translate {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let moved =
+            ray::Ray::new(ray.orig - self.offset, ray.direction, ray.time).with_seed(ray.seed);
+        let mut hit = self.object.intersect(&moved, t_min, t_max)?;
+        hit.point += self.offset;
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let bbox = self.object.bounding_box()?;
+        Some(aabb::Aabb::new(
+            bbox.min + self.offset,
+            bbox.max + self.offset,
+        ))
+    }
+}
+
+// Rotates `object` about the y axis, anticlockwise looking down from +y.
+pub struct RotateY {
+    object: Box<dyn Object + Sync>,
+    sin_theta: f32,
+    cos_theta: f32,
+    bbox: Option<aabb::Aabb>,
+}
+
+impl RotateY {
+    // `angle` is in degrees.
+    pub fn new(object: Box<dyn Object + Sync>, angle: f32) -> RotateY {
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        let mut rotated = RotateY {
+            object,
+            sin_theta,
+            cos_theta,
+            bbox: None,
+        };
+        // The box around the rotated corners of the object's box.
+        rotated.bbox = rotated.object.bounding_box().map(|bbox| {
+            let mut min = na::Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+            let mut max = na::Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+            for &x in &[bbox.min[0], bbox.max[0]] {
+                for &y in &[bbox.min[1], bbox.max[1]] {
+                    for &z in &[bbox.min[2], bbox.max[2]] {
+                        let corner = rotated.to_world(na::Vector3::new(x, y, z));
+                        min = min.inf(&corner.into());
+                        max = max.sup(&corner.into());
+                    }
+                }
+            }
+            aabb::Aabb::new(min, max)
+        });
+        rotated
+    }
+
+    fn to_object(&self, v: na::Vector3<f32>) -> na::Vector3<f32> {
+        na::Vector3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn to_world(&self, v: na::Vector3<f32>) -> na::Vector3<f32> {
+        na::Vector3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+}
+
+impl Object for RotateY {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // Rotations keep lengths, so distances along the two rays agree.
+        let rotated = ray::Ray::new(
+            self.to_object(ray.orig.coords).into(),
+            self.to_object(ray.direction),
+            ray.time,
+        )
+        .with_seed(ray.seed);
+        let mut hit = self.object.intersect(&rotated, t_min, t_max)?;
+        hit.point = self.to_world(hit.point.coords).into();
+        hit.normal = self.to_world(hit.normal);
+        hit.tangents = hit
+            .tangents
+            .map(|(dpdu, dpdv)| (self.to_world(dpdu), self.to_world(dpdv)));
+        hit.incoming = ray.direction;
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::testing::{grey, ray};
+    use crate::{Sphere, T_MIN};
+
+    #[test]
+    fn translated_sphere_is_hit_at_offset() {
+        let moved = Translate {
+            object: Box::new(Sphere {
+                centre: na::Point3::origin(),
+                radius: 1.0,
+                material: grey(),
+            }),
+            offset: na::Vector3::new(0.0, 0.0, -5.0),
+        };
+        let hit = moved
+            .intersect(
+                &ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert!((hit.point - na::Point3::new(0.0, 0.0, -4.0)).norm() < 1e-5);
+        assert!(hit.front_face);
+        assert!((hit.normal - na::Vector3::z()).norm() < 1e-5);
+        let bbox = moved.bounding_box().unwrap();
+        assert_eq!(bbox.min, na::Point3::new(-1.0, -1.0, -6.0));
+        assert_eq!(bbox.max, na::Point3::new(1.0, 1.0, -4.0));
+    }
+
+    #[test]
+    fn quarter_turn_maps_box_and_normal() {
+        // A quarter turn takes +x to -z and +z to +x.
+        let turned = RotateY::new(
+            Box::new(Cube::new(
+                na::Point3::new(0.0, 0.0, 0.0),
+                na::Point3::new(2.0, 1.0, 1.0),
+                grey(),
+            )),
+            90.0,
+        );
+        let bbox = turned.bounding_box().unwrap();
+        assert!((bbox.min - na::Point3::new(0.0, 0.0, -2.0)).norm() < 1e-5);
+        assert!((bbox.max - na::Point3::new(1.0, 1.0, 0.0)).norm() < 1e-5);
+        // The face at x = 0 now faces +z.
+        let hit = turned
+            .intersect(
+                &ray([0.5, 0.5, 5.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        assert!((hit.point - na::Point3::new(0.5, 0.5, 0.0)).norm() < 1e-5);
+        assert!(hit.front_face);
+        assert!((hit.normal - na::Vector3::z()).norm() < 1e-5);
+        assert_eq!(hit.incoming, -na::Vector3::z());
+    }
+}
@@ -0,0 +1,203 @@
+use nalgebra as na;
+use rand::Rng;
+use serde_derive::*;
+
+use crate::color;
+use crate::image_texture;
+use crate::ray;
+use crate::{nearest_intersection, HitRecord, Object, Sampleable, T_MIN};
+
+// A light with no size. In scene files a spotlight is told apart from a
+// point light by its `direction` and `cutoff_angle`.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Light {
+    Spot(SpotLight),
+    Point(PointLight),
+}
+
+impl Light {
+    // Irradiance from this light reaching a diffuse surface at `hit`, scaled
+    // by the surface's BRDF without its albedo (1/pi for a Lambertian one) so
+    // it can be multiplied by the albedo.
+    // Returns black if the light is behind the surface or occluded.
+    pub fn illuminate(
+        &self,
+        hit: &HitRecord,
+        world: &(dyn Object + Sync),
+        rng: &mut rand::rngs::SmallRng,
+    ) -> color::Color {
+        match self {
+            Light::Point(light) => light.illuminate(hit, world, rng),
+            Light::Spot(light) => light.illuminate(hit, world, rng),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct PointLight {
+    pub position: na::Point3<f32>,
+    pub intensity: color::Color,
+}
+
+impl PointLight {
+    fn illuminate(
+        &self,
+        hit: &HitRecord,
+        world: &(dyn Object + Sync),
+        rng: &mut rand::rngs::SmallRng,
+    ) -> color::Color {
+        let black = color::Color::new(0.0, 0.0, 0.0);
+        let to_light = self.position - hit.point;
+        let distance = to_light.norm();
+        let cos_theta = hit.normal.dot(&to_light) / distance;
+        if cos_theta <= 0.0 {
+            return black;
+        }
+
+        // Start the shadow ray slightly off the surface so that it does not
+        // hit the surface it starts from.
+        let shadow_ray =
+            ray::Ray::new(hit.point + T_MIN * hit.normal, to_light, hit.time).with_seed(rng.gen());
+        if nearest_intersection(&shadow_ray, world, T_MIN, distance - T_MIN).is_some() {
+            return black;
+        }
+
+        let weight = hit.diffuse_weight(to_light);
+        self.intensity * (weight * cos_theta / (std::f32::consts::PI * distance.powi(2)))
+    }
+}
+
+// A point light shining along `direction` into a cone whose half-angle is
+// `cutoff_angle` degrees. With a `falloff_angle` the light fades smoothly
+// from that angle out to the cutoff; otherwise the cone has a hard edge.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct SpotLight {
+    pub position: na::Point3<f32>,
+    pub direction: na::Vector3<f32>,
+    pub cutoff_angle: f32,
+    #[serde(default)]
+    pub falloff_angle: Option<f32>,
+    pub intensity: color::Color,
+}
+
+impl SpotLight {
+    // How much of the light reaches `pt`: 1 inside the cone, 0 outside it
+    // and a smoothstep across the falloff band.
+    fn cone_factor(&self, pt: na::Point3<f32>) -> f32 {
+        let angle = (pt - self.position).angle(&self.direction).to_degrees();
+        match self.falloff_angle {
+            Some(inner) if inner < self.cutoff_angle => {
+                let x = ((self.cutoff_angle - angle) / (self.cutoff_angle - inner)).clamp(0.0, 1.0);
+                x * x * (3.0 - 2.0 * x)
+            }
+            _ if angle <= self.cutoff_angle => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn illuminate(
+        &self,
+        hit: &HitRecord,
+        world: &(dyn Object + Sync),
+        rng: &mut rand::rngs::SmallRng,
+    ) -> color::Color {
+        let factor = self.cone_factor(hit.point);
+        if factor <= 0.0 {
+            return color::Color::new(0.0, 0.0, 0.0);
+        }
+        let point = PointLight {
+            position: self.position,
+            intensity: self.intensity,
+        };
+        factor * point.illuminate(hit, world, rng)
+    }
+}
+
+// Whether the hit at distance `t` along `ray` lies on `light`, rather than on
+// something else in front of or behind it.
+pub fn is_hit_on(light: &(dyn Sampleable + Sync), ray: &ray::Ray, t: f32) -> bool {
+    light
+        .intersect(ray, T_MIN, f32::INFINITY)
+        .is_some_and(|hit| (hit.t - t).abs() <= 1e-4 * t.max(1.0))
+}
+
+// Light from the emissive `light` reaching a diffuse surface at `hit`,
+// estimated from one random point on the light and scaled by the BRDF
+// like `Light::illuminate`. Black if the point is behind the surface or
+// occluded.
+pub fn sample_area_light(
+    light: &(dyn Sampleable + Sync),
+    hit: &HitRecord,
+    world: &(dyn Object + Sync),
+    filter: image_texture::Filter,
+    rng: &mut rand::rngs::SmallRng,
+) -> color::Color {
+    let black = color::Color::new(0.0, 0.0, 0.0);
+    let direction = light.random(hit.point, rng);
+    let cos_theta = hit.normal.dot(&direction.normalize());
+    let pdf = light.pdf_value(hit.point, direction);
+    if cos_theta <= 0.0 || pdf <= 0.0 {
+        return black;
+    }
+
+    let shadow_ray =
+        ray::Ray::new(hit.point + T_MIN * hit.normal, direction, hit.time).with_seed(rng.gen());
+    match nearest_intersection(&shadow_ray, world, T_MIN, f32::INFINITY) {
+        Some(light_hit) if is_hit_on(light, &shadow_ray, light_hit.t) => {
+            let weight = hit.diffuse_weight(direction);
+            light_hit.emitted(filter) * (weight * cos_theta / (std::f32::consts::PI * pdf))
+        }
+        _ => black,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot(falloff_angle: Option<f32>) -> SpotLight {
+        SpotLight {
+            position: na::Point3::new(0.0, 2.0, 0.0),
+            direction: na::Vector3::new(0.0, -1.0, 0.0),
+            cutoff_angle: 30.0,
+            falloff_angle,
+            intensity: color::Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // The point on the floor at `degrees` from the spot's axis.
+    fn floor_at(degrees: f32) -> na::Point3<f32> {
+        na::Point3::new(2.0 * degrees.to_radians().tan(), 0.0, 0.0)
+    }
+
+    #[test]
+    fn hard_cone() {
+        assert_eq!(spot(None).cone_factor(floor_at(0.0)), 1.0);
+        assert_eq!(spot(None).cone_factor(floor_at(29.0)), 1.0);
+        assert_eq!(spot(None).cone_factor(floor_at(31.0)), 0.0);
+    }
+
+    #[test]
+    fn smooth_falloff() {
+        let light = spot(Some(20.0));
+        assert_eq!(light.cone_factor(floor_at(10.0)), 1.0);
+        assert!((light.cone_factor(floor_at(25.0)) - 0.5).abs() < 1e-3);
+        assert_eq!(light.cone_factor(floor_at(35.0)), 0.0);
+    }
+
+    #[test]
+    fn scene_lights_keep_point_form() {
+        let point: Light = serde_json::from_str(
+            r#"{"position": [0, 1, 0], "intensity": {"red": 1, "green": 1, "blue": 1}}"#,
+        )
+        .unwrap();
+        assert!(matches!(point, Light::Point(_)));
+        let spot: Light = serde_json::from_str(
+            r#"{"position": [0, 1, 0], "direction": [0, -1, 0], "cutoff_angle": 20,
+                "intensity": {"red": 1, "green": 1, "blue": 1}}"#,
+        )
+        .unwrap();
+        assert!(matches!(spot, Light::Spot(_)));
+    }
+}
@@ -1,288 +1,2488 @@
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
 use itertools::Itertools;
 use nalgebra as na;
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
 use serde_derive::*;
-use serde_json::*;
 
+mod aabb;
+mod background;
+mod bloom;
+mod bvh;
+mod camera;
+mod cli;
+mod cmj;
 mod color;
+mod cone;
+mod cube;
+mod cylinder;
+mod denoise;
+mod disk;
+mod edges;
+mod environment;
+mod image_texture;
+mod instance;
+mod light;
+mod medium;
+mod moving_sphere;
+mod obj;
+mod perlin;
+mod plane;
+mod primitive;
 mod ray;
+mod rect;
+mod scene;
+#[cfg(test)]
+mod testing;
+mod texture;
+mod triangle;
+mod wireframe;
 
 #[derive(Clone, Copy, Deserialize, Serialize)]
 enum MaterialType {
     Lambertian,
+    // A rough diffuse surface, following the Oren-Nayar model of a surface
+    // made of tiny Lambertian facets. The roughness is the standard deviation
+    // of the facet slopes in radians; at 0 it is Lambertian. Rough surfaces
+    // stay brighter towards the edge of the lit side.
+    OrenNayar(f32),
     Metal(f32),
+    // A metal whose texture gives its reflectance head-on (F0), rising to
+    // white at grazing angles following Schlick's approximation. Takes a fuzz
+    // like `Metal`, which reflects the same at every angle.
+    FresnelMetal(f32),
+    Dielectric(f32),
+    // The metallic-roughness model of glTF, with the texture as the base
+    // colour. At `metallic` 0 it is a diffuse base under a clear coat that
+    // reflects 4% head on, and at 1 a metal reflecting in the base colour.
+    // `roughness` spreads the reflection from a mirror at 0. The reflection
+    // is Cook-Torrance, with the GGX distribution, Smith shadowing and
+    // Schlick's Fresnel.
+    Pbr { metallic: f32, roughness: f32 },
+    Emissive,
+    // Scatters in a uniformly random direction, as inside a participating
+    // medium.
+    Isotropic,
+}
+
+fn both_sides() -> bool {
+    true
 }
 
 #[derive(Clone, Copy, Deserialize, Serialize)]
 struct Material {
     material_type: MaterialType,
-    color: color::Color,
+    texture: texture::Texture,
+    // Whether an emissive surface gives off light from its back as well as
+    // its front, which is the side its geometric normal points to.
+    #[serde(default = "both_sides")]
+    two_sided: bool,
+    // A tangent-space normal map: red, green and blue from 0 to 1 bend the
+    // normal along the directions of increasing u and v and along itself,
+    // with (0.5, 0.5, 1) leaving it as it is. Only surfaces that know how
+    // their uv coordinates run across them (spheres, triangles and
+    // rectangles) use it.
+    #[serde(default)]
+    normal_map: Option<texture::Texture>,
+}
+
+impl Material {
+    fn emitted(
+        &self,
+        uv: (f32, f32),
+        pt: na::Point3<f32>,
+        filter: image_texture::Filter,
+    ) -> color::Color {
+        match self.material_type {
+            MaterialType::Emissive => self.texture.value(uv, pt, filter),
+            _ => color::Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// A uniformly distributed direction. Points are drawn from the unit ball
+// rather than the cube around it, which would favour the cube's corners, and
+// points too close to the centre to normalize reliably are drawn again.
+fn random_unit_vector(rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+    loop {
+        let p = na::Vector3::new(
+            rng.gen_range(-1f32, 1f32),
+            rng.gen_range(-1f32, 1f32),
+            rng.gen_range(-1f32, 1f32),
+        );
+        let norm_squared = p.norm_squared();
+        if norm_squared < 1.0 && norm_squared > 1e-12 {
+            return p / norm_squared.sqrt();
+        }
+    }
+}
+
+fn reflect(v: na::Vector3<f32>, n: na::Vector3<f32>) -> na::Vector3<f32> {
+    v - 2f32 * n.dot(&v) * n
+}
+
+fn refract(
+    v: na::Vector3<f32>,
+    n: na::Vector3<f32>,
+    cos_theta: f32,
+    ratio: f32,
+) -> na::Vector3<f32> {
+    let r_out_perp = ratio * (v + cos_theta * n);
+    let r_out_parallel = -(1.0 - r_out_perp.norm_squared()).abs().sqrt() * n;
+    r_out_perp + r_out_parallel
+}
+
+// Schlick's approximation for the reflectance of a dielectric.
+fn reflectance(cos_theta: f32, ratio: f32) -> f32 {
+    let r0 = ((1.0 - ratio) / (1.0 + ratio)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
 }
 
-fn random_unit_vector(rng: &mut rand::rngs::ThreadRng) -> na::Vector3<f32> {
-    let x: f32 = rng.gen_range(-1f32, 1f32);
-    let y: f32 = rng.gen_range(-1f32, 1f32);
-    let z: f32 = rng.gen_range(-1f32, 1f32);
+// When the random vector almost cancels the normal the sum cannot be
+// normalized, so fall back to scattering along the normal.
+fn lambertian_direction(
+    random_unit: na::Vector3<f32>,
+    normal: na::Vector3<f32>,
+) -> na::Vector3<f32> {
+    let direction = random_unit + normal;
+    if direction.norm() < 1e-8 {
+        normal
+    } else {
+        direction
+    }
+}
+
+// Two unit vectors perpendicular to the unit vector `n` and to each other.
+fn orthonormal_basis(n: na::Vector3<f32>) -> (na::Vector3<f32>, na::Vector3<f32>) {
+    let helper = if n[0].abs() > 0.9 {
+        na::Vector3::y()
+    } else {
+        na::Vector3::x()
+    };
+    let t = n.cross(&helper).normalize();
+    (t, n.cross(&t))
+}
 
-    na::Vector3::new(x, y, z).normalize()
+// A direction in the hemisphere around `normal` drawn with probability
+// proportional to the cosine with the normal, by lifting a uniform point on
+// the unit disk onto the hemisphere (Malley's method).
+fn cosine_hemisphere_direction(
+    rng: &mut rand::rngs::SmallRng,
+    normal: na::Vector3<f32>,
+) -> na::Vector3<f32> {
+    let r2: f32 = rng.gen();
+    let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+    let r = r2.sqrt();
+    let (t, b) = orthonormal_basis(normal);
+    r * phi.cos() * t + r * phi.sin() * b + (1.0 - r2).max(0.0).sqrt() * normal
+}
+
+// A direction in the hemisphere around `normal` with every direction equally
+// likely.
+fn uniform_hemisphere_direction(
+    rng: &mut rand::rngs::SmallRng,
+    normal: na::Vector3<f32>,
+) -> na::Vector3<f32> {
+    let direction = random_unit_vector(rng);
+    if direction.dot(&normal) < 0.0 {
+        -direction
+    } else {
+        direction
+    }
+}
+
+// The GGX width of a PBR surface, from its perceptual roughness. A little
+// width is kept even at zero, where the distribution would be infinitely
+// sharp.
+fn ggx_alpha(roughness: f32) -> f32 {
+    roughness.clamp(0.0, 1.0).powi(2).max(1e-3)
+}
+
+// The GGX density of microfacets facing along the half vector `h`. The sine is
+// taken from the cross product, since one minus the squared cosine loses
+// everything near the peak of a sharp distribution.
+fn ggx_distribution(n: na::Vector3<f32>, h: na::Vector3<f32>, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let denom = n.dot(&h).powi(2) * alpha2 + n.cross(&h).norm_squared();
+    alpha2 / (std::f32::consts::PI * denom * denom)
+}
+
+// Smith's fraction of microfacets seen from a direction at `cos_theta` to the
+// normal, for GGX.
+fn smith_g1(cos_theta: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    2.0 * cos_theta / (cos_theta + (alpha2 + (1.0 - alpha2) * cos_theta * cos_theta).sqrt())
+}
+
+// A half vector drawn from the GGX distribution around `normal`, with
+// density `ggx_distribution * cos` over solid angle.
+fn ggx_half_vector(
+    rng: &mut rand::rngs::SmallRng,
+    normal: na::Vector3<f32>,
+    alpha: f32,
+) -> na::Vector3<f32> {
+    let xi: f32 = rng.gen();
+    let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+    let denom = 1.0 + (alpha * alpha - 1.0) * xi;
+    let cos_theta = ((1.0 - xi) / denom).sqrt();
+    let sin_theta = (alpha * alpha * xi / denom).sqrt();
+    let (t, b) = orthonormal_basis(normal);
+    sin_theta * phi.cos() * t + sin_theta * phi.sin() * b + cos_theta * normal
+}
+
+// How often a PBR surface scatters by its specular reflection rather than as
+// a diffuse surface. Metals have no diffuse part at all.
+fn pbr_specular_chance(metallic: f32) -> f32 {
+    0.5 + 0.5 * metallic.clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DiffuseMode {
+    // The normal plus a random unit vector, which only approximates the
+    // Lambertian cosine distribution.
+    UnitVector,
+    // Exact cosine-weighted sampling of the hemisphere.
+    Cosine,
+    // Every direction in the hemisphere equally likely, with the colour
+    // unweighted, so light from grazing angles counts as much as light from
+    // overhead and surfaces look flatter.
+    UniformHemisphere,
+}
+
+impl std::str::FromStr for DiffuseMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DiffuseMode, ()> {
+        match s {
+            "unit-vector" => Ok(DiffuseMode::UnitVector),
+            "cosine" => Ok(DiffuseMode::Cosine),
+            "hemisphere" => Ok(DiffuseMode::UniformHemisphere),
+            _ => Err(()),
+        }
+    }
 }
 
 fn scatter(
-    rng: &mut rand::rngs::ThreadRng,
+    rng: &mut rand::rngs::SmallRng,
     in_ray: ray::Ray,
-    intersection_pt: na::Point3<f32>,
-    normal_vec: na::Vector3<f32>,
-    material: Material,
-) -> ray::Ray {
-    match material.material_type {
-        MaterialType::Lambertian => {
-            ray::Ray::new(intersection_pt, random_unit_vector(rng) + normal_vec)
+    hit: &HitRecord,
+    diffuse: DiffuseMode,
+) -> Option<ray::Ray> {
+    let intersection_pt = hit.point;
+    let normal_vec = hit.normal;
+    let scattered = match hit.material.material_type {
+        MaterialType::Lambertian | MaterialType::OrenNayar(_) => {
+            let direction = match diffuse {
+                DiffuseMode::UnitVector => {
+                    lambertian_direction(random_unit_vector(rng), normal_vec)
+                }
+                DiffuseMode::Cosine => cosine_hemisphere_direction(rng, normal_vec),
+                DiffuseMode::UniformHemisphere => uniform_hemisphere_direction(rng, normal_vec),
+            };
+            Some(ray::Ray::new(intersection_pt, direction, in_ray.time))
+        }
+        MaterialType::Metal(fuzziness) | MaterialType::FresnelMetal(fuzziness) => {
+            // Fuzz above 1 would let most reflections point into the surface,
+            // and negative fuzz means nothing.
+            let fuzziness = fuzziness.clamp(0.0, 1.0);
+            let direction =
+                reflect(in_ray.direction, normal_vec) + fuzziness * random_unit_vector(rng);
+            // A fuzzed reflection that ends up below the surface is absorbed.
+            if direction.dot(&normal_vec) > 0.0 {
+                Some(ray::Ray::new(intersection_pt, direction, in_ray.time))
+            } else {
+                None
+            }
+        }
+        MaterialType::Dielectric(refraction_index) => {
+            let ratio = if hit.front_face {
+                1.0 / refraction_index
+            } else {
+                refraction_index
+            };
+            let cos_theta = (-in_ray.direction).dot(&normal_vec).min(1.0);
+            let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+            let direction = if ratio * sin_theta > 1.0 || reflectance(cos_theta, ratio) > rng.gen()
+            {
+                reflect(in_ray.direction, normal_vec)
+            } else {
+                refract(in_ray.direction, normal_vec, cos_theta, ratio)
+            };
+            Some(ray::Ray::new(intersection_pt, direction, in_ray.time))
         }
-        MaterialType::Metal(fuzziness) => ray::Ray::new(
+        MaterialType::Pbr {
+            metallic,
+            roughness,
+        } => {
+            let direction = if rng.gen::<f32>() < pbr_specular_chance(metallic) {
+                let h = ggx_half_vector(rng, normal_vec, ggx_alpha(roughness));
+                reflect(in_ray.direction, h)
+            } else {
+                cosine_hemisphere_direction(rng, normal_vec)
+            };
+            // Like a fuzzed metal, a reflection off a facet that points
+            // below the surface is absorbed.
+            if direction.dot(&normal_vec) > 0.0 {
+                Some(ray::Ray::new(intersection_pt, direction, in_ray.time))
+            } else {
+                None
+            }
+        }
+        MaterialType::Emissive => None,
+        MaterialType::Isotropic => Some(ray::Ray::new(
             intersection_pt,
-            in_ray.direction - 2f32 * normal_vec.dot(&in_ray.direction) * normal_vec
-                + fuzziness * random_unit_vector(rng),
-        ),
+            random_unit_vector(rng),
+            in_ray.time,
+        )),
+    };
+    scattered.map(|scattered| scattered.with_seed(rng.gen()))
+}
+
+#[derive(Clone, Copy)]
+struct HitRecord {
+    t: f32,
+    point: na::Point3<f32>,
+    // Always points against the incoming ray. The geometric (outward) normal
+    // is `normal` when `front_face` is set and `-normal` otherwise.
+    normal: na::Vector3<f32>,
+    front_face: bool,
+    // Direction of the ray that made the hit.
+    incoming: na::Vector3<f32>,
+    // Time of the ray that made the hit, passed on to scattered and shadow rays.
+    time: f32,
+    // Surface coordinates of the hit in [0, 1] x [0, 1], used for texturing.
+    uv: (f32, f32),
+    // Directions along the surface in which u and v increase, for the normal
+    // map. Surfaces that don't know them leave them out.
+    tangents: Option<(na::Vector3<f32>, na::Vector3<f32>)>,
+    material: Material,
+}
+
+impl HitRecord {
+    fn new(
+        ray: &ray::Ray,
+        t: f32,
+        outward_normal: na::Vector3<f32>,
+        uv: (f32, f32),
+        material: Material,
+    ) -> HitRecord {
+        let front_face = ray.direction.dot(&outward_normal) < 0.0;
+        HitRecord {
+            t,
+            point: ray.at(t),
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            front_face,
+            incoming: ray.direction,
+            time: ray.time,
+            uv,
+            tangents: None,
+            material,
+        }
+    }
+
+    fn with_tangents(mut self, dpdu: na::Vector3<f32>, dpdv: na::Vector3<f32>) -> HitRecord {
+        self.tangents = Some((dpdu, dpdv));
+        self
+    }
+
+    // Bends the normal by the material's normal map, if it has one and the
+    // surface gave its tangents.
+    fn with_normal_map(mut self, filter: image_texture::Filter) -> HitRecord {
+        let (map, (dpdu, dpdv)) = match (self.material.normal_map, self.tangents) {
+            (Some(map), Some(tangents)) => (map, tangents),
+            _ => return self,
+        };
+        let outward = if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        let tangent = dpdu - outward.dot(&dpdu) * outward;
+        // Where u stops changing, at the poles of a sphere, there is no
+        // tangent to bend along.
+        if tangent.norm_squared() < 1e-12 {
+            return self;
+        }
+        let tangent = tangent.normalize();
+        let mut bitangent = outward.cross(&tangent);
+        if bitangent.dot(&dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+        let bend =
+            map.value(self.uv, self.point, filter).to_vector() * 2.0 - na::Vector3::repeat(1.0);
+        let bent = bend[0] * tangent + bend[1] * bitangent + bend[2] * outward;
+        let bent = if self.front_face { bent } else { -bent };
+        // A normal bent away from the viewer would send light into the
+        // surface, so such hits keep their own.
+        if bent.norm_squared() > 1e-12 && bent.dot(&self.incoming) < 0.0 {
+            self.normal = bent.normalize();
+        }
+        self
+    }
+
+    fn color(&self, filter: image_texture::Filter) -> color::Color {
+        self.material.texture.value(self.uv, self.point, filter)
+    }
+
+    // The light given off towards the ray that made the hit.
+    fn emitted(&self, filter: image_texture::Filter) -> color::Color {
+        if self.front_face || self.material.two_sided {
+            self.material.emitted(self.uv, self.point, filter)
+        } else {
+            color::Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    // The fraction of light carried on by a ray scattered from this hit
+    // along `outgoing`.
+    fn attenuation(
+        &self,
+        outgoing: na::Vector3<f32>,
+        filter: image_texture::Filter,
+    ) -> color::Color {
+        match self.material.material_type {
+            MaterialType::FresnelMetal(_) => {
+                let f0 = self.color(filter);
+                let cos_theta = (-self.incoming).dot(&self.normal).clamp(0.0, 1.0);
+                f0 + (color::Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta).powi(5)
+            }
+            MaterialType::Pbr {
+                metallic,
+                roughness,
+            } => self.pbr_attenuation(metallic, roughness, outgoing, filter),
+            _ => self.diffuse_weight(outgoing) * self.color(filter),
+        }
+    }
+
+    // The Cook-Torrance BRDF plus the diffuse base, times the cosine towards
+    // `outgoing`, over the density with which `scatter` picks that direction
+    // from either of its two lobes.
+    fn pbr_attenuation(
+        &self,
+        metallic: f32,
+        roughness: f32,
+        outgoing: na::Vector3<f32>,
+        filter: image_texture::Filter,
+    ) -> color::Color {
+        let black = color::Color::new(0.0, 0.0, 0.0);
+        let white = color::Color::new(1.0, 1.0, 1.0);
+        let n = self.normal;
+        let to_viewer = -self.incoming.normalize();
+        let outgoing = outgoing.normalize();
+        let (cos_out, cos_view) = (n.dot(&outgoing), n.dot(&to_viewer));
+        // Interpolated normals can leave the viewer below the surface.
+        if cos_out <= 0.0 || cos_view <= 0.0 {
+            return black;
+        }
+        let h = (to_viewer + outgoing).normalize();
+        let cos_half = to_viewer.dot(&h);
+
+        let metallic = metallic.clamp(0.0, 1.0);
+        let alpha = ggx_alpha(roughness);
+        let base = self.color(filter);
+        let f0 = color::Color::lerp(color::Color::new(0.04, 0.04, 0.04), base, metallic);
+        let schlick = |cos: f32| f0 + (white - f0) * (1.0 - cos).powi(5);
+        let d = ggx_distribution(n, h, alpha);
+        let g = smith_g1(cos_view, alpha) * smith_g1(cos_out, alpha);
+        let specular = schlick(cos_half) * (d * g / (4.0 * cos_view * cos_out));
+        // Light that the coat doesn't reflect towards the viewer reaches the
+        // base. Taking the Fresnel term at the viewing angle rather than the
+        // half vector keeps the two from adding up to more than came in.
+        let diffuse =
+            (white - schlick(cos_view)) * base * ((1.0 - metallic) / std::f32::consts::PI);
+
+        let chance = pbr_specular_chance(metallic);
+        let pdf = chance * d * n.dot(&h) / (4.0 * cos_half)
+            + (1.0 - chance) * cos_out / std::f32::consts::PI;
+        if pdf <= 0.0 {
+            return black;
+        }
+        (specular + diffuse) * (cos_out / pdf)
+    }
+
+    // How a diffuse surface's reflectance towards the viewer, for light
+    // arriving from `to_light`, compares to a Lambertian one of the same
+    // colour. Always 1 except for Oren-Nayar.
+    fn diffuse_weight(&self, to_light: na::Vector3<f32>) -> f32 {
+        let sigma2 = match self.material.material_type {
+            MaterialType::OrenNayar(roughness) => roughness.powi(2),
+            _ => return 1.0,
+        };
+        let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let n = self.normal;
+        let to_light = to_light.normalize();
+        let to_viewer = -self.incoming.normalize();
+        let cos_in = n.dot(&to_light).clamp(-1.0, 1.0);
+        let cos_out = n.dot(&to_viewer).clamp(-1.0, 1.0);
+        // Only the difference between the two azimuths matters, found from
+        // the directions projected onto the surface.
+        let tangent_in = to_light - cos_in * n;
+        let tangent_out = to_viewer - cos_out * n;
+        let cos_phi = if tangent_in.norm_squared() > 1e-12 && tangent_out.norm_squared() > 1e-12 {
+            tangent_in
+                .normalize()
+                .dot(&tangent_out.normalize())
+                .max(0.0)
+        } else {
+            0.0
+        };
+        let (theta_in, theta_out) = (cos_in.acos(), cos_out.acos());
+        let alpha = theta_in.max(theta_out);
+        let beta = theta_in.min(theta_out);
+        a + b * cos_phi * alpha.sin() * beta.tan()
     }
 }
 
 trait Object {
-    fn intersect(&self, ray: &ray::Ray) -> Option<f32>;
-    fn normal(&self, pt: na::Point3<f32>) -> na::Vector3<f32>;
-    fn get_color(&self) -> color::Color;
-    fn get_material(&self) -> Material;
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Option<aabb::Aabb>;
 }
 
-#[derive(Clone, Copy)]
+// Objects that can be aimed at by random directions, so that they can be
+// sampled directly as area lights.
+trait Sampleable: Object {
+    // A direction from `origin` towards a random point on the object.
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32>;
+    // Probability density, per unit solid angle, of `random` returning
+    // `direction` from `origin`. Zero if the direction misses the object.
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32;
+}
+
+impl<T: Object> Object for [T] {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut nearest: Option<HitRecord> = None;
+        for o in self {
+            if let Some(hit) = o.intersect(ray, t_min, nearest.map_or(t_max, |h| h.t)) {
+                nearest = Some(hit);
+            }
+        }
+        nearest
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let mut boxes = self.iter().map(|o| o.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(aabb::Aabb::surrounding_box(acc, b?)))
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
 struct Sphere {
     centre: na::Point3<f32>,
     radius: f32,
     material: Material,
 }
 
+impl Sphere {
+    fn uv(&self, pt: na::Point3<f32>) -> (f32, f32) {
+        // Polar angle measured from -y and azimuth around y starting at -x.
+        // Clamping keeps rounding errors at the poles from producing NaNs.
+        let p = (pt - self.centre).normalize();
+        let theta = (-p[1]).clamp(-1.0, 1.0).acos();
+        let phi = (-p[2]).atan2(p[0]) + std::f32::consts::PI;
+        (
+            phi / (2.0 * std::f32::consts::PI),
+            theta / std::f32::consts::PI,
+        )
+    }
+}
+
 impl Object for Sphere {
-    fn intersect(&self, ray: &ray::Ray) -> Option<f32> {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let oc = ray.orig - self.centre;
         let c = oc.norm().powi(2) - self.radius.powi(2);
         let half_b = oc.dot(&ray.direction);
         let determinant = half_b.powi(2) - c;
         if determinant < 0.0 {
-            None
-        } else {
-            let val = -half_b - determinant.sqrt();
-            if val >= 0.0 {
-                Some(val)
-            } else {
-                None
-            }
+            return None;
+        }
+        // Take the near root, or the far one when the near one is behind
+        // `t_min`, as it is for a ray starting inside the sphere.
+        let sqrt_d = determinant.sqrt();
+        let mut t = -half_b - sqrt_d;
+        if t < t_min {
+            t = -half_b + sqrt_d;
         }
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let pt = ray.at(t);
+        // Dividing by the signed radius makes a negative radius flip the
+        // normal inwards, which is what a hollow glass sphere needs.
+        let outward_normal = (pt - self.centre) / self.radius;
+        // u runs around the y axis and v up it.
+        let p = pt - self.centre;
+        let dpdu = na::Vector3::new(p[2], 0.0, -p[0]);
+        Some(
+            HitRecord::new(ray, t, outward_normal, self.uv(pt), self.material)
+                .with_tangents(dpdu, p.cross(&dpdu)),
+        )
     }
 
-    fn normal(&self, pt: na::Point3<f32>) -> na::Vector3<f32> {
-        (pt - self.centre).normalize()
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let r = na::Vector3::repeat(self.radius.abs());
+        Some(aabb::Aabb::new(self.centre - r, self.centre + r))
     }
+}
 
-    fn get_color(&self) -> color::Color {
-        self.material.color
+// Seen from outside, a sphere covers a cone of directions, which is sampled
+// uniformly so that a shading point gets light from the whole visible cap
+// and a larger sphere casts softer shadows. From inside, every direction
+// reaches it.
+impl Sampleable for Sphere {
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        let to_centre = self.centre - origin;
+        let distance_squared = to_centre.norm_squared();
+        if distance_squared <= self.radius.powi(2) {
+            return random_unit_vector(rng);
+        }
+        let cos_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        let cos_theta = 1.0 + rng.gen::<f32>() * (cos_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+        let w = to_centre.normalize();
+        let (u, v) = orthonormal_basis(w);
+        (phi.cos() * sin_theta) * u + (phi.sin() * sin_theta) * v + cos_theta * w
     }
 
-    fn get_material(&self) -> Material {
-        self.material
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        let ray = ray::Ray::new(origin, direction, 0.0);
+        if self.intersect(&ray, T_MIN, f32::INFINITY).is_none() {
+            return 0.0;
+        }
+        let distance_squared = (self.centre - origin).norm_squared();
+        if distance_squared <= self.radius.powi(2) {
+            return 1.0 / (4.0 * std::f32::consts::PI);
+        }
+        let cos_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        1.0 / (2.0 * std::f32::consts::PI * (1.0 - cos_max))
     }
 }
 
-fn nearest_intersection<'a>(
+// Hits closer than this are treated as the ray re-intersecting the surface it
+// just left, which otherwise shows up as shadow acne.
+const T_MIN: f32 = 1e-3;
+
+fn nearest_intersection(
     ray: &ray::Ray,
-    objs: &'a [Box<dyn Object + Sync>],
-) -> Option<(
-    na::Point3<f32>,
-    na::Vector3<f32>,
-    &'a Box<dyn Object + Sync>,
-)> {
-    let mut nearest_obj: Option<&Box<dyn Object + Sync>> = None;
-    let mut tmin: Option<f32> = None;
-    for o in objs {
-        if let Some(t) = o.intersect(ray) {
-            if tmin.is_none() || t < tmin.unwrap() {
-                tmin = Some(t);
-                nearest_obj = Some(o);
+    world: &(dyn Object + Sync),
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord> {
+    world.intersect(ray, t_min, t_max)
+}
+
+// SplitMix64 finalizer, used to decorrelate the per-pixel RNG seeds.
+fn mix_seed(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RenderMode {
+    // Full path tracing.
+    Shaded,
+    // Colour each pixel by the outward normal at the first hit, mapped from
+    // [-1, 1] to [0, 1]. Handy for checking a new `Object`'s normals.
+    Normals,
+}
+
+impl std::str::FromStr for RenderMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RenderMode, ()> {
+        match s {
+            "shaded" => Ok(RenderMode::Shaded),
+            "normals" => Ok(RenderMode::Normals),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sampler {
+    // Every sample lands anywhere in the pixel.
+    Random,
+    // The largest square number of samples is spread over an NxN grid with
+    // one jittered sample per cell; any left over are random.
+    Stratified,
+    // Correlated multi-jittered samples, stratified over a grid as well as
+    // along each axis. Every `samples_per_pixel` samples make up one set.
+    MultiJittered,
+}
+
+impl std::str::FromStr for Sampler {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Sampler, ()> {
+        match s {
+            "random" => Ok(Sampler::Random),
+            "stratified" => Ok(Sampler::Stratified),
+            "cmj" => Ok(Sampler::MultiJittered),
+            _ => Err(()),
+        }
+    }
+}
+
+// Stops sampling a pixel once the standard error of its mean colour is at
+// most `threshold` in every channel, after at least `min_samples` samples.
+// `samples_per_pixel` remains the upper limit.
+#[derive(Clone, Copy)]
+struct Adaptive {
+    threshold: f32,
+    min_samples: u32,
+}
+
+#[derive(Clone, Copy)]
+struct RenderSettings {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    dither: bool,
+    tone_map: bool,
+    gamma: f32,
+    // Every pixel is multiplied by this before tone mapping and gamma: the
+    // exposure times the gain of each channel.
+    exposure: color::Color,
+    mode: RenderMode,
+    sampler: Sampler,
+    diffuse: DiffuseMode,
+    // How image textures, normal maps and environment maps are looked up.
+    texture_filter: image_texture::Filter,
+    // Number of bounces after which Russian roulette may end a path, or None
+    // to only stop at `max_depth`.
+    roulette_depth: Option<u32>,
+    // Paths that escape to the sky before scattering this many times add
+    // nothing for it, leaving only light that has bounced around the scene.
+    min_bounces: u32,
+    // Trace a single ray through the centre of each pixel and of the lens,
+    // giving a quick aliased look at the scene.
+    preview: bool,
+    // Don't report progress on stderr.
+    quiet: bool,
+    binary_ppm: bool,
+    // Write camera rays that miss everything as transparent instead of
+    // showing the background. Needs PNG output.
+    transparent: bool,
+    adaptive: Option<Adaptive>,
+    // Samples brighter than this are scaled down to it before averaging,
+    // trading a little energy for fewer fireflies.
+    firefly_clamp: Option<f32>,
+    bloom: Option<bloom::Bloom>,
+    denoise: Option<denoise::Denoise>,
+    edges: Option<edges::Edges>,
+    // Outline these boxes over the finished image.
+    wireframe: Option<wireframe::Wireframe>,
+    // Save the render to `<output>.checkpoint` every this many samples per
+    // pixel.
+    checkpoint: Option<u32>,
+    // Carry on from the checkpoint instead of starting over. Without this an
+    // existing checkpoint is an error, so that rerunning a finished render
+    // neither adds to it unasked nor throws it away.
+    resume: bool,
+    // Also write how many samples each pixel took to `<output>_samples`.
+    sample_map: bool,
+    // Every sample gets its own RNG seeded from this, the pixel index and the
+    // sample number, so the output only depends on the seed and not on how
+    // rayon schedules pixels.
+    seed: u64,
+}
+
+// What has been gathered for one pixel so far.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+struct PixelState {
+    color: color::Accumulator,
+    // How many of the `taken` camera rays hit geometry.
+    hits: u32,
+    taken: u32,
+}
+
+impl PixelState {
+    // Whether the mean is known to within `threshold`. With a transparent
+    // background only the hits add colour, so the share of hits, which
+    // gives the alpha, must have settled too; a pixel that only sees the
+    // background has no colour to wait for.
+    fn converged(&self, threshold: f32, transparent: bool) -> bool {
+        if !transparent {
+            return self.color.standard_error() <= threshold;
+        }
+        if self.taken < 2 {
+            return false;
+        }
+        let coverage = self.hits as f32 / self.taken as f32;
+        let coverage_error = (coverage * (1.0 - coverage) / (self.taken - 1) as f32).sqrt();
+        coverage_error <= threshold && (self.hits == 0 || self.color.standard_error() <= threshold)
+    }
+}
+
+// A render in progress, saved so that more samples can be added later.
+#[derive(Deserialize, Serialize)]
+struct Checkpoint {
+    width: u32,
+    height: u32,
+    seed: u64,
+    // Samples per pixel taken by the passes so far, not counting the extra
+    // samples of edge pixels, so that the next run carries on from there.
+    samples: u32,
+    pixels: Vec<PixelState>,
+}
+
+impl Checkpoint {
+    // Where --checkpoint keeps the render written to `outputfile`.
+    fn path(outputfile: &str) -> String {
+        format!("{}.checkpoint", outputfile)
+    }
+
+    // None if there is no checkpoint at `path` yet.
+    fn load(path: &str, width: u32, height: u32) -> std::io::Result<Option<Checkpoint>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let checkpoint: Checkpoint = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if checkpoint.width != width
+            || checkpoint.height != height
+            || checkpoint.pixels.len() != (width * height) as usize
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "checkpoint {} is for a {}x{} image, not {}x{}",
+                    path, checkpoint.width, checkpoint.height, width, height
+                ),
+            ));
+        }
+        Ok(Some(checkpoint))
+    }
+
+    // Combines renders of the same image made with different seeds, as if
+    // all their samples had been taken in one go. The result carries on with
+    // the first seed, whose later sample numbers none of them used.
+    fn merge(checkpoints: Vec<(String, Checkpoint)>) -> std::io::Result<Checkpoint> {
+        let mut checkpoints = checkpoints.into_iter();
+        let (first_path, mut merged) = checkpoints.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "nothing to merge")
+        })?;
+        let mut seeds = vec![(merged.seed, first_path)];
+        for (path, checkpoint) in checkpoints {
+            // The same seed gives the same samples, which would only
+            // pretend to lower the noise.
+            if let Some((_, other)) = seeds.iter().find(|(seed, _)| *seed == checkpoint.seed) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} and {} were rendered with the same seed", other, path),
+                ));
+            }
+            for (pixel, other) in merged.pixels.iter_mut().zip(&checkpoint.pixels) {
+                pixel.color.merge(&other.color);
+                pixel.hits += other.hits;
+                pixel.taken += other.taken;
             }
+            merged.samples += checkpoint.samples;
+            seeds.push((checkpoint.seed, path));
         }
+        Ok(merged)
     }
 
-    match (nearest_obj, tmin) {
-        (Some(o), Some(t)) => Some((ray.at(t), o.normal(ray.at(t)), o)),
-        (_, _) => None,
+    // Writes to a temporary file first, so that being interrupted while
+    // saving leaves the previous checkpoint intact.
+    fn save(&self, path: &str) -> std::io::Result<()> {
+        let temporary = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()?;
+        drop(out);
+        std::fs::rename(temporary, path)
     }
 }
 
+// A finished 8-bit image, row by row from the top, with an alpha channel
+// when the background is transparent.
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 3]>,
+    alpha: Option<Vec<u8>>,
+    // How many samples each pixel took.
+    samples: Vec<u32>,
+}
+
+impl Image {
+    fn write(&self, outputfile: &str, binary_ppm: bool) -> std::io::Result<()> {
+        write_image(
+            outputfile,
+            self.width,
+            self.height,
+            &self.pixels,
+            self.alpha.as_deref(),
+            binary_ppm,
+        )
+    }
+
+    // Writes the image, and the map of its sample counts beside it if
+    // `settings` asks for one.
+    fn save(&self, outputfile: &str, settings: &RenderSettings) -> std::io::Result<()> {
+        self.write(outputfile, settings.binary_ppm)?;
+        if settings.sample_map {
+            write_image(
+                &suffixed_path(outputfile, "samples"),
+                self.width,
+                self.height,
+                &sample_heat_map(&self.samples),
+                None,
+                settings.binary_ppm,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Each pixel's sample count as a fraction of the largest, coloured black at
+// none, then red, yellow and finally white for the pixels that took the most.
+fn sample_heat_map(samples: &[u32]) -> Vec<[u8; 3]> {
+    let stops = [
+        color::Color::new(0.0, 0.0, 0.0),
+        color::Color::new(1.0, 0.0, 0.0),
+        color::Color::new(1.0, 1.0, 0.0),
+        color::Color::new(1.0, 1.0, 1.0),
+    ];
+    let most = samples.iter().copied().max().unwrap_or(0).max(1);
+    samples
+        .iter()
+        .map(|&count| {
+            let position = count as f32 / most as f32 * (stops.len() - 1) as f32;
+            let stop = (position as usize).min(stops.len() - 2);
+            color::Color::lerp(stops[stop], stops[stop + 1], position - stop as f32).to_rgb8()
+        })
+        .collect()
+}
+
 fn raytracing_ppm<F>(
     outputfile: &str,
-    aspect_ratio: f32,
-    img_height: u32,
-    viewport_height: f32,
+    camera: &camera::Camera,
+    settings: &RenderSettings,
+    overlay: Option<&wireframe::Overlay>,
     ray_color: F,
 ) -> std::io::Result<()>
 where
-    F: Fn(ray::Ray, &mut rand::rngs::ThreadRng) -> color::Color + Sync,
+    F: Fn(ray::Ray, &mut rand::rngs::SmallRng) -> (color::Color, bool) + Sync,
+{
+    render_to_buffer(outputfile, camera, settings, overlay, ray_color)?.save(outputfile, settings)
+}
+
+// Renders the image in memory, post-processing included. `name` is only
+// used for progress messages and, when `settings.checkpoint` is set, for the
+// checkpoint kept in `<name>.checkpoint`; nothing else touches the disk.
+fn render_to_buffer<F>(
+    name: &str,
+    camera: &camera::Camera,
+    settings: &RenderSettings,
+    overlay: Option<&wireframe::Overlay>,
+    ray_color: F,
+) -> std::io::Result<Image>
+where
+    // Gives the colour seen along a camera ray and whether the ray hit any
+    // geometry.
+    F: Fn(ray::Ray, &mut rand::rngs::SmallRng) -> (color::Color, bool) + Sync,
 {
-    let img_width: u32 = (img_height as f32 * aspect_ratio) as u32;
+    let img_width = settings.width;
+    let img_height = settings.height;
+    let samples_per_pixel = settings.samples_per_pixel;
+    if samples_per_pixel == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "samples_per_pixel must be at least 1",
+        ));
+    }
 
-    let viewport_width: f32 = viewport_height * aspect_ratio;
-    let focal_length: f32 = 1.0;
+    // Side of the sample grid in each pixel; zero for purely random samples.
+    let strata = match settings.sampler {
+        Sampler::Random | Sampler::MultiJittered => 0,
+        Sampler::Stratified => (samples_per_pixel as f32).sqrt() as u32,
+    };
 
-    let origin: na::Vector3<f32> = na::Vector3::new(0.0, 0.0, 0.0);
-    let vertical: na::Vector3<f32> = na::Vector3::y() * viewport_height as f32;
-    let horizontal: na::Vector3<f32> = na::Vector3::x() * viewport_width as f32;
-    let lower_left_corner = na::Vector3::new(0.0, 0.0, 0.0)
-        - vertical / 2.0
-        - horizontal / 2.0
-        - na::Vector3::z() * focal_length;
+    // With checkpoints the samples are taken in passes, saving what has been
+    // gathered after each one. Resuming from an earlier checkpoint takes
+    // every pixel to `samples_per_pixel` more than its passes had asked for.
+    let checkpoint = settings
+        .checkpoint
+        .map(|interval| {
+            if name == "-" {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "checkpoints need an output file",
+                ));
+            }
+            Ok((Checkpoint::path(name), interval))
+        })
+        .transpose()?;
+    let resumed = match &checkpoint {
+        Some((path, _)) => match Checkpoint::load(path, img_width, img_height)? {
+            Some(_) if !settings.resume => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists; add --resume to add samples to it, or delete it to start over",
+                        path
+                    ),
+                ));
+            }
+            None if settings.resume => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no checkpoint {} to resume", path),
+                ));
+            }
+            resumed => resumed,
+        },
+        None => None,
+    };
+    let total = (img_width * img_height) as usize;
+    let (seed, done_samples, mut pixels) = match resumed {
+        Some(resumed) => (resumed.seed, resumed.samples, resumed.pixels),
+        None => (settings.seed, 0, vec![PixelState::default(); total]),
+    };
+    let pass_samples = checkpoint
+        .as_ref()
+        .map_or(samples_per_pixel, |&(_, interval)| interval);
+    let target = done_samples + samples_per_pixel;
+    let passes = samples_per_pixel.div_ceil(pass_samples);
 
-    let samples_per_pixel = 500u32;
+    // Takes samples of pixel `i` until it has `until` of them, or adaptive
+    // sampling finds it has enough.
+    let sample_pixel = |i: usize, state: &mut PixelState, until: u32| {
+        let (row, column) = (img_height - 1 - i as u32 / img_width, i as u32 % img_width);
+        let pixel_seed = mix_seed(seed ^ mix_seed(i as u64));
+        while state.taken < until {
+            if let Some(adaptive) = settings.adaptive {
+                if state.taken >= adaptive.min_samples.max(1)
+                    && state.converged(adaptive.threshold, settings.transparent)
+                {
+                    break;
+                }
+            }
+            // Each sample has its own RNG, so with random sampling the result
+            // doesn't depend on how the samples were split between runs.
+            let sample = state.taken;
+            let mut rng = rand::rngs::SmallRng::seed_from_u64(mix_seed(pixel_seed ^ sample as u64));
+            let (du, dv): (f32, f32) = if settings.preview {
+                (0.5, 0.5)
+            } else if settings.sampler == Sampler::MultiJittered {
+                // Sets are as long as this run's --samples, so resuming with
+                // another --samples lays the samples out differently from
+                // one run of the total. A fixed set size would avoid that,
+                // but renders with fewer samples than a set would then be
+                // little better than random ones.
+                let set = (sample / samples_per_pixel) as u64;
+                let pattern = mix_seed(pixel_seed ^ mix_seed(set)) as u32;
+                cmj::sample(sample % samples_per_pixel, samples_per_pixel, pattern)
+            } else if sample < strata * strata {
+                let cell_u = (sample % strata) as f32 + rng.gen::<f32>();
+                let cell_v = (sample / strata) as f32 + rng.gen::<f32>();
+                (cell_u / strata as f32, cell_v / strata as f32)
+            } else {
+                (rng.gen(), rng.gen())
+            };
+            let u: f32 = (column as f32 + du) / (img_width - 1) as f32;
+            let v: f32 = (row as f32 + dv) / (img_height - 1) as f32;
+            let current_ray = if settings.preview {
+                camera.get_pinhole_ray(u, v)
+            } else {
+                camera.get_ray(u, v, &mut rng)
+            };
+            let (mut sample_color, hit) = ray_color(current_ray, &mut rng);
+            if let Some(max) = settings.firefly_clamp {
+                let brightness = sample_color.luminance();
+                if brightness > max {
+                    sample_color = sample_color * (max / brightness);
+                }
+            }
+            if hit {
+                state.hits += 1;
+            }
+            // With a transparent background the misses only lower the alpha,
+            // so the hits keep their full colour at the edges.
+            if hit || !settings.transparent {
+                state.color.add(sample_color);
+            }
+            state.taken += 1;
+        }
+    };
+    let save = |pixels: &[PixelState], samples: u32| match &checkpoint {
+        Some((path, _)) => Checkpoint {
+            width: img_width,
+            height: img_height,
+            seed,
+            samples,
+            pixels: pixels.to_vec(),
+        }
+        .save(path),
+        None => Ok(()),
+    };
 
-    let colors: Vec<color::Color> = (0..img_height)
-        .rev()
-        .cartesian_product(0..img_width)
-        .collect::<Vec<(u32, u32)>>()
-        .into_par_iter()
-        .map(|x| -> color::Color {
-            let mut col = color::Color::new(0.0, 0.0, 0.0);
-            let mut rng = rand::thread_rng();
-            for _ in 0..samples_per_pixel {
-                let mut r: f32 = rng.gen();
-                let u: f32 = (x.1 as f32 + r) / (img_width - 1) as f32;
-                r = rng.gen();
-                let v: f32 = (x.0 as f32 + r) / (img_height - 1) as f32;
-                let current_ray = ray::Ray::new(
-                    na::Point3::from(origin),
-                    lower_left_corner + u * horizontal + v * vertical - origin,
-                );
-                col += ray_color(current_ray, &mut rng);
+    let done = AtomicUsize::new(0);
+    for pass in 0..passes {
+        let until = target.min(done_samples + (pass + 1) * pass_samples);
+        pixels.par_iter_mut().enumerate().for_each(|(i, state)| {
+            sample_pixel(i, state, until);
+            if !settings.quiet {
+                // Only the pixel that crosses into a new percent prints.
+                let units = total * passes as usize;
+                let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                if finished * 100 / units != (finished - 1) * 100 / units {
+                    eprint!("\rRendering {}: {}%", name, finished * 100 / units);
+                }
+            }
+        });
+        save(&pixels, until)?;
+    }
+
+    // Edge pixels are sampled up to `edges.samples` in all, however many of
+    // them came from earlier runs, so resuming does not add them twice.
+    if let Some(edges) = settings.edges {
+        let means: Vec<color::Color> = pixels.iter().map(|p| p.color.mean()).collect();
+        let flagged = edges.detect(&means, img_width, img_height);
+        if !settings.quiet {
+            let count = flagged.iter().filter(|&&f| f).count();
+            eprint!("\rRendering {}: refining {} edge pixels", name, count);
+        }
+        pixels
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(i, _)| flagged[*i])
+            .for_each(|(i, state)| sample_pixel(i, state, edges.samples));
+        save(&pixels, target)?;
+    }
+    if !settings.quiet {
+        eprintln!();
+    }
+    Ok(develop(settings, overlay, &pixels))
+}
+
+// Turns the gathered samples into the final image and writes it out.
+fn finish_image(
+    outputfile: &str,
+    settings: &RenderSettings,
+    overlay: Option<&wireframe::Overlay>,
+    pixels: &[PixelState],
+) -> std::io::Result<()> {
+    develop(settings, overlay, pixels).save(outputfile, settings)
+}
+
+// Turns the gathered samples into the final image.
+fn develop(
+    settings: &RenderSettings,
+    overlay: Option<&wireframe::Overlay>,
+    pixels: &[PixelState],
+) -> Image {
+    let (img_width, img_height) = (settings.width, settings.height);
+    let samples: Vec<u32> = pixels.iter().map(|p| p.taken).collect();
+    let mut colors: Vec<color::Color> = pixels.iter().map(|p| p.color.mean()).collect();
+    // The fraction of each pixel's camera rays that hit something.
+    let coverage: Vec<f32> = pixels
+        .iter()
+        .map(|p| p.hits as f32 / p.taken as f32)
+        .collect();
+
+    if let Some(denoise) = settings.denoise {
+        denoise.apply(&mut colors, img_width, img_height);
+    }
+    if let Some(bloom) = settings.bloom {
+        bloom.apply(&mut colors, img_width, img_height);
+    }
+
+    let mut pixels: Vec<[u8; 3]> = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let mut color = color * settings.exposure;
+            if settings.tone_map {
+                color.tone_map_reinhard();
+            }
+            color.gamma_correction(settings.gamma);
+            color.clamp();
+            if settings.dither {
+                color.to_rgb8_dithered(i as u32 % img_width, i as u32 / img_width)
+            } else {
+                color.to_rgb8()
             }
-            col / samples_per_pixel as f32
         })
         .collect();
 
-    let mut outfile = File::create(outputfile)?;
-    writeln!(outfile, "P3\n{} {}\n{}", img_width, img_height, u8::MAX)?;
+    if let Some(overlay) = overlay {
+        overlay.draw(&mut pixels, img_width, img_height);
+    }
+
+    let alpha: Option<Vec<u8>> = if settings.transparent {
+        Some(
+            coverage
+                .iter()
+                .map(|c| (u8::MAX as f32 * c).round() as u8)
+                .collect(),
+        )
+    } else {
+        None
+    };
+    Image {
+        width: img_width,
+        height: img_height,
+        pixels,
+        alpha,
+        samples,
+    }
+}
+
+// PPM output is plain-text P3 unless `binary_ppm` asks for P6. An `alpha`
+// channel can only be written to PNG. An `outputfile` of `-` writes a PPM
+// image to stdout.
+fn write_image(
+    outputfile: &str,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    alpha: Option<&[u8]>,
+    binary_ppm: bool,
+) -> std::io::Result<()> {
+    let png = Path::new(outputfile).extension().and_then(|e| e.to_str()) == Some("png");
+    if alpha.is_some() && !png {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "a transparent background needs .png output",
+        ));
+    }
+
+    let mut out: Box<dyn Write> = if outputfile == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(BufWriter::new(File::create(outputfile)?))
+    };
+    if png {
+        write_png(&mut out, width, height, pixels, alpha)?;
+    } else if binary_ppm {
+        write_ppm_binary(&mut out, width, height, pixels)?;
+    } else {
+        write_ppm(&mut out, width, height, pixels)?;
+    }
+    out.flush()
+}
 
-    for mut color in colors {
-        color.gamma_correction();
-        color.clamp();
-        writeln!(outfile, "{}", color)?;
+fn write_ppm(
+    out: &mut dyn Write,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+) -> std::io::Result<()> {
+    writeln!(out, "P3\n{} {}\n{}", width, height, u8::MAX)?;
+
+    for [r, g, b] in pixels {
+        writeln!(out, "{} {} {}", r, g, b)?;
     }
 
     Ok(())
 }
 
-fn raytracing(
-    aspect_ratio: f32,
+fn write_ppm_binary(
+    out: &mut dyn Write,
+    width: u32,
     height: u32,
-    view_port_height: f32,
-    objects: &[Box<dyn Object + Sync>],
+    pixels: &[[u8; 3]],
+) -> std::io::Result<()> {
+    write!(out, "P6\n{} {}\n{}\n", width, height, u8::MAX)?;
+    out.write_all(&pixels.concat())
+}
+
+// RGB, or RGBA when `alpha` is given.
+fn write_png(
+    out: &mut dyn Write,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    alpha: Option<&[u8]>,
+) -> std::io::Result<()> {
+    use image::ImageEncoder;
+
+    let (bytes, color_type) = match alpha {
+        Some(alpha) => (
+            pixels
+                .iter()
+                .zip(alpha)
+                .flat_map(|([r, g, b], a)| [*r, *g, *b, *a])
+                .collect(),
+            image::ExtendedColorType::Rgba8,
+        ),
+        None => (pixels.concat(), image::ExtendedColorType::Rgb8),
+    };
+    image::codecs::png::PngEncoder::new(out)
+        .write_image(&bytes, width, height, color_type)
+        .map_err(std::io::Error::other)
+}
+
+// Tells the user on stdout that `filename` was written, unless the image
+// itself went to stdout.
+fn report(filename: &str, result: std::io::Result<()>) {
+    match result {
+        Ok(()) if filename == "-" => {}
+        Ok(()) => println!("Printed {}", filename),
+        Err(e) if filename == "-" => eprintln!("Error happened while printing to stdout: {}", e),
+        Err(e) => println!("Error happened while printing {}: {}", filename, e),
+    }
+}
+
+fn raytracing(
+    camera: &camera::Camera,
+    settings: &RenderSettings,
+    world: &bvh::World,
+    lights: &[light::Light],
+    area_lights: &[Box<dyn Sampleable + Sync>],
+    background: &background::Background,
     filename: &str,
 ) {
-    match raytracing_ppm(
-        filename,
-        aspect_ratio,
-        height,
-        view_port_height,
-        |r: ray::Ray, rng: &mut rand::rngs::ThreadRng| -> color::Color {
-            let mut used_ray = r;
-            let mut count: i32 = 0;
-            let max_depth = 20;
-            let mut col = color::Color::new(1f32, 1f32, 1f32);
-            for _ in 0..max_depth {
-                if let Some((intersect_pt, normal_vec, nearest_obj)) =
-                    nearest_intersection(&used_ray, &objects)
+    let overlay = settings
+        .wireframe
+        .map(|wireframe| wireframe::Overlay::new(wireframe, camera, world));
+    let result = raytracing_ppm(filename, camera, settings, overlay.as_ref(), |r, rng| {
+        path_color(r, rng, settings, world, lights, area_lights, background)
+    });
+    report(filename, result);
+}
+
+// The colour seen along the camera ray `r`, and whether it hit anything.
+fn path_color(
+    r: ray::Ray,
+    rng: &mut rand::rngs::SmallRng,
+    settings: &RenderSettings,
+    world: &(dyn Object + Sync),
+    lights: &[light::Light],
+    area_lights: &[Box<dyn Sampleable + Sync>],
+    background: &background::Background,
+) -> (color::Color, bool) {
+    let filter = settings.texture_filter;
+    if settings.mode == RenderMode::Normals {
+        return match nearest_intersection(&r, world, T_MIN, f32::INFINITY) {
+            Some(hit) => {
+                let hit = hit.with_normal_map(filter);
+                let outward = if hit.front_face {
+                    hit.normal
+                } else {
+                    -hit.normal
+                };
+                (0.5 * color::Color::from(outward.add_scalar(1.0)), true)
+            }
+            None => (background.value(r.direction, filter), false),
+        };
+    }
+
+    let mut used_ray = r;
+    let mut col = color::Color::new(1f32, 1f32, 1f32);
+    let mut emitted = color::Color::new(0f32, 0f32, 0f32);
+    // Whether the area lights were sampled at the last bounce, in which
+    // case finding one now would count its light twice.
+    let mut sampled_lights = false;
+    for depth in 0..settings.max_depth {
+        match nearest_intersection(&used_ray, world, T_MIN, f32::INFINITY) {
+            Some(hit) => {
+                let hit = hit.with_normal_map(filter);
+                let on_sampled_light = sampled_lights
+                    && area_lights
+                        .iter()
+                        .any(|l| light::is_hit_on(l.as_ref(), &used_ray, hit.t));
+                if !on_sampled_light {
+                    emitted += col * hit.emitted(filter);
+                }
+                sampled_lights = false;
+                if let MaterialType::Lambertian | MaterialType::OrenNayar(_) =
+                    hit.material.material_type
                 {
-                    used_ray = scatter(
-                        rng,
-                        used_ray,
-                        intersect_pt,
-                        normal_vec,
-                        nearest_obj.get_material(),
-                    );
-                    col *= nearest_obj.get_color();
-                    count += 1;
+                    let mut direct = lights
+                        .iter()
+                        .map(|light| light.illuminate(&hit, world, rng))
+                        .fold(color::Color::new(0.0, 0.0, 0.0), |a, b| a + b);
+                    for area_light in area_lights {
+                        direct +=
+                            light::sample_area_light(area_light.as_ref(), &hit, world, filter, rng);
+                    }
+                    sampled_lights = !area_lights.is_empty();
+                    emitted += col * hit.color(filter) * direct;
+                }
+                match scatter(rng, used_ray, &hit, settings.diffuse) {
+                    Some(scattered) => used_ray = scattered,
+                    None => return (emitted, true),
+                }
+                col *= hit.attenuation(used_ray.direction, filter);
+                // Russian roulette: keep the path with a probability
+                // equal to its brightest throughput channel and make
+                // up for the lost paths by boosting the survivors, so
+                // the estimate stays unbiased. A path that can no
+                // longer carry light always stops.
+                if settings
+                    .roulette_depth
+                    .is_some_and(|start| depth + 1 >= start)
+                {
+                    let survival = col.max_channel().min(1.0);
+                    if survival.is_nan() || survival <= 0.0 || rng.gen::<f32>() >= survival {
+                        return (emitted, true);
+                    }
+                    col = col / survival;
+                }
+            }
+            None => {
+                // `depth` is the number of times the path has
+                // scattered before escaping.
+                let sky = if depth < settings.min_bounces {
+                    color::Color::new(0.0, 0.0, 0.0)
                 } else {
-                    break;
+                    background.value(used_ray.direction, filter)
+                };
+                return (emitted + col * sky, depth > 0);
+            }
+        }
+    }
+    // The path is still bouncing around the scene after max_depth
+    // scatterings, so it never reaches the sky and adds no more light.
+    (emitted, true)
+}
+
+// Writes the distance to the first hit of each pixel's centre ray as a
+// grayscale image, black at `near` and white at `far` (or the other way round
+// with `invert`). Misses are written as `far`. By default the range runs from
+// the nearest hit to the 95th percentile of hit distances, so that the last few
+// pixels of an infinite plane running off to the horizon don't squash
+// everything else into black.
+fn depth_map(
+    outputfile: &str,
+    camera: &camera::Camera,
+    settings: &RenderSettings,
+    world: &(dyn Object + Sync),
+    near: Option<f32>,
+    far: Option<f32>,
+    invert: bool,
+) -> std::io::Result<()> {
+    let img_width = settings.width;
+    let img_height = settings.height;
+
+    let depths: Vec<Option<f32>> = (0..img_height)
+        .rev()
+        .cartesian_product(0..img_width)
+        .collect::<Vec<(u32, u32)>>()
+        .into_par_iter()
+        .map(|x| {
+            let u = (x.1 as f32 + 0.5) / (img_width - 1) as f32;
+            let v = (x.0 as f32 + 0.5) / (img_height - 1) as f32;
+            let ray = camera.get_pinhole_ray(u, v);
+            nearest_intersection(&ray, world, T_MIN, f32::INFINITY).map(|hit| hit.t)
+        })
+        .collect();
+
+    let mut hits: Vec<f32> = depths.iter().flatten().copied().collect();
+    hits.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let near = near.unwrap_or_else(|| hits.first().copied().unwrap_or(0.0));
+    let far = far.unwrap_or_else(|| {
+        hits.get(hits.len() * 95 / 100)
+            .or_else(|| hits.last())
+            .copied()
+            .unwrap_or(1.0)
+    });
+
+    let pixels: Vec<[u8; 3]> = depths
+        .into_iter()
+        .map(|depth| {
+            let mut d = match depth {
+                Some(t) if far > near => ((t - near) / (far - near)).clamp(0.0, 1.0),
+                Some(_) => 0.0,
+                None => 1.0,
+            };
+            if invert {
+                d = 1.0 - d;
+            }
+            color::Color::new(d, d, d).to_rgb8()
+        })
+        .collect();
+
+    write_image(
+        outputfile,
+        img_width,
+        img_height,
+        &pixels,
+        None,
+        settings.binary_ppm,
+    )
+}
+
+// Most camera rays per pixel that `first_hit_maps` traces.
+const FIRST_HIT_SAMPLES: u32 = 16;
+
+// The outward normal and the albedo where each pixel's camera rays first hit
+// something, the auxiliary images that denoisers such as OIDN take alongside
+// the noisy render. Normals are mapped from [-1, 1] to [0, 1] and are zero
+// for misses. The albedo is the texture colour at the hit, or the background
+// for misses, with the render's gamma. Each pixel averages up to
+// FIRST_HIT_SAMPLES rays jittered like the render's, so edges are smoothed
+// the same way, and no ray bounces.
+fn first_hit_maps(
+    camera: &camera::Camera,
+    settings: &RenderSettings,
+    world: &(dyn Object + Sync),
+    background: &background::Background,
+) -> (Vec<[u8; 3]>, Vec<[u8; 3]>) {
+    let (img_width, img_height) = (settings.width, settings.height);
+    let samples = if settings.preview {
+        1
+    } else {
+        settings.samples_per_pixel.clamp(1, FIRST_HIT_SAMPLES)
+    };
+    (0..img_width * img_height)
+        .into_par_iter()
+        .map(|i| {
+            let (row, column) = (img_height - 1 - i / img_width, i % img_width);
+            let mut rng =
+                rand::rngs::SmallRng::seed_from_u64(mix_seed(settings.seed ^ mix_seed(i as u64)));
+            let mut normal = na::Vector3::zeros();
+            let mut albedo = color::Color::new(0.0, 0.0, 0.0);
+            for _ in 0..samples {
+                let (du, dv) = if settings.preview {
+                    (0.5, 0.5)
+                } else {
+                    (rng.gen(), rng.gen())
+                };
+                let u = (column as f32 + du) / (img_width - 1) as f32;
+                let v = (row as f32 + dv) / (img_height - 1) as f32;
+                let ray = if settings.preview {
+                    camera.get_pinhole_ray(u, v)
+                } else {
+                    camera.get_ray(u, v, &mut rng)
+                };
+                match nearest_intersection(&ray, world, T_MIN, f32::INFINITY) {
+                    Some(hit) => {
+                        let hit = hit.with_normal_map(settings.texture_filter);
+                        normal += if hit.front_face {
+                            hit.normal
+                        } else {
+                            -hit.normal
+                        };
+                        albedo += hit.color(settings.texture_filter);
+                    }
+                    None => albedo += background.value(ray.direction, settings.texture_filter),
                 }
             }
-            if count == max_depth + 1 {
-                color::Color::new(0.0, 0.0, 0.0)
+            let mut normal = 0.5 * color::Color::from((normal / samples as f32).add_scalar(1.0));
+            let mut albedo = albedo / samples as f32;
+            albedo.gamma_correction(settings.gamma);
+            normal.clamp();
+            albedo.clamp();
+            (normal.to_rgb8(), albedo.to_rgb8())
+        })
+        .unzip()
+}
+
+// `frame_0001.png` for frame 1 of `frame.png`.
+fn frame_path(path: &str, frame: u32) -> String {
+    suffixed_path(path, &format!("{:04}", frame))
+}
+
+// `render_normal.png` for the suffix `normal` on `render.png`.
+fn suffixed_path(path: &str, suffix: &str) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn main() -> std::io::Result<()> {
+    let args = match cli::Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::usage());
+            std::process::exit(2);
+        }
+    };
+    if args.help || args.scenes.is_empty() {
+        println!("{}", cli::usage());
+        return Ok(());
+    }
+
+    // Zero threads lets rayon pick one per core. The thread count never
+    // changes the image: every sample draws from its own RNG seeded from the
+    // render seed, the pixel index and the sample number.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads.unwrap_or(0))
+        .build()
+        .map_err(std::io::Error::other)?;
+    for path in &args.scenes {
+        let mut scene = scene::Scene::load(path)?;
+        let world =
+            bvh::World::with_split(scene.objects()?, args.bvh.unwrap_or(bvh::Split::Median));
+        let area_lights = scene.area_lights();
+        let settings = RenderSettings {
+            width: args.width.unwrap_or(scene.width),
+            height: args.height.unwrap_or(scene.height),
+            samples_per_pixel: if args.preview {
+                1
             } else {
-                let t = 0.5 * (used_ray.direction[1] + 1.0);
-                col * ((1.0f32 - t) * color::Color::new(1f32, 1f32, 1f32)
-                    + t * color::Color::new(0.5f32, 0.7f32, 1f32))
+                args.samples.unwrap_or(scene.samples_per_pixel)
+            },
+            max_depth: args.max_depth.unwrap_or(cli::DEFAULT_MAX_DEPTH),
+            dither: scene.dither,
+            tone_map: args.tone_map || scene.tone_map,
+            gamma: args.gamma.unwrap_or(cli::DEFAULT_GAMMA),
+            exposure: args.exposure.unwrap_or(1.0)
+                * args.gains.unwrap_or(color::Color::new(1.0, 1.0, 1.0)),
+            mode: args.mode.unwrap_or(RenderMode::Shaded),
+            sampler: args.sampler.unwrap_or(Sampler::Random),
+            diffuse: args.diffuse.unwrap_or(DiffuseMode::UnitVector),
+            texture_filter: if args.nearest_textures {
+                image_texture::Filter::Nearest
+            } else {
+                image_texture::Filter::Bilinear
+            },
+            roulette_depth: args.roulette,
+            min_bounces: args.min_bounces.unwrap_or(0),
+            preview: args.preview,
+            quiet: args.quiet,
+            binary_ppm: args.binary_ppm,
+            transparent: args.transparent,
+            adaptive: args.adaptive.map(|threshold| Adaptive {
+                threshold,
+                min_samples: args
+                    .adaptive_min
+                    .unwrap_or(cli::DEFAULT_ADAPTIVE_MIN_SAMPLES),
+            }),
+            firefly_clamp: args.firefly_clamp,
+            bloom: args.bloom.map(|threshold| bloom::Bloom {
+                threshold,
+                radius: args.bloom_radius.unwrap_or(cli::DEFAULT_BLOOM_RADIUS),
+            }),
+            denoise: args.denoise.map(|sigma| denoise::Denoise {
+                radius: args.denoise_radius.unwrap_or(cli::DEFAULT_DENOISE_RADIUS),
+                sigma,
+            }),
+            edges: args.edges.map(|threshold| edges::Edges {
+                threshold,
+                samples: args.edge_samples.unwrap_or(cli::DEFAULT_EDGE_SAMPLES),
+            }),
+            wireframe: args.wireframe,
+            checkpoint: args.checkpoint,
+            resume: args.resume,
+            sample_map: args.sample_map,
+            seed: args.seed.unwrap_or_else(rand::random),
+        };
+        let aspect_ratio = settings.width as f32 / settings.height as f32;
+        if args.fit_camera {
+            match world.bounded_box() {
+                Some(bbox) => {
+                    if let Err(e) = scene.fit_camera(&bbox, aspect_ratio, cli::FIT_CAMERA_MARGIN) {
+                        eprintln!("{}: {}", path, e);
+                    }
+                }
+                None => eprintln!("{}: nothing to fit the camera to", path),
             }
-        },
-    ) {
-        Ok(()) => println!("Printed {}", filename),
-        Err(e) => println!("Error happened while printing {}: {}", filename, e),
+        }
+        let output = args.output.as_ref().unwrap_or(&scene.output);
+        if !args.merge.is_empty() {
+            let checkpoints = args
+                .merge
+                .iter()
+                .map(
+                    |path| match Checkpoint::load(path, settings.width, settings.height)? {
+                        Some(checkpoint) => Ok((path.clone(), checkpoint)),
+                        None => Err(std::io::Error::new(
+                            std::io::ErrorKind::NotFound,
+                            format!("no checkpoint {}", path),
+                        )),
+                    },
+                )
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let merged = Checkpoint::merge(checkpoints)?;
+            if settings.checkpoint.is_some() {
+                merged.save(&Checkpoint::path(output))?;
+            }
+            let camera = scene.camera(aspect_ratio, 0.0);
+            let overlay = settings
+                .wireframe
+                .map(|wireframe| wireframe::Overlay::new(wireframe, &camera, &world));
+            report(
+                output,
+                finish_image(output, &settings, overlay.as_ref(), &merged.pixels),
+            );
+            continue;
+        }
+        let frames = args.frames.unwrap_or(scene.frames);
+        if frames == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "frames must be at least 1",
+            ));
+        }
+        // Scene files can ask for frames too, which stdout has no names for.
+        if frames > 1 && output == "-" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "an animation needs an output file to number, not stdout",
+            ));
+        }
+        for frame in 0..frames {
+            let camera = scene.camera(aspect_ratio, frame as f32 / frames as f32);
+            let numbered = |path: &str| {
+                if frames > 1 {
+                    frame_path(path, frame + 1)
+                } else {
+                    path.to_string()
+                }
+            };
+            pool.install(|| {
+                raytracing(
+                    &camera,
+                    &settings,
+                    &world,
+                    &scene.lights,
+                    &area_lights,
+                    &scene.background,
+                    &numbered(output),
+                );
+                if let Some(depth) = &args.depth {
+                    let depth = numbered(depth);
+                    let result = depth_map(
+                        &depth,
+                        &camera,
+                        &settings,
+                        &world,
+                        args.depth_near,
+                        args.depth_far,
+                        args.depth_invert,
+                    );
+                    report(&depth, result);
+                }
+                if args.aovs {
+                    let output = numbered(output);
+                    let (normals, albedo) =
+                        first_hit_maps(&camera, &settings, &world, &scene.background);
+                    for (suffix, pixels) in [("normal", normals), ("albedo", albedo)] {
+                        let path = suffixed_path(&output, suffix);
+                        let result = write_image(
+                            &path,
+                            settings.width,
+                            settings.height,
+                            &pixels,
+                            None,
+                            settings.binary_ppm,
+                        );
+                        report(&path, result);
+                    }
+                }
+            });
+        }
     }
+
+    Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    let aspect_ratio: f32 = 16.0 / 9.0;
-    let height: u32 = 256;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ray;
+    use rand::SeedableRng;
+
+    fn sphere() -> Sphere {
+        Sphere {
+            centre: na::Point3::new(0.0, 0.0, -5.0),
+            radius: 1.0,
+            material: testing::grey(),
+        }
+    }
+
+    #[test]
+    fn sphere_hit_returns_near_side() {
+        let hit = sphere()
+            .intersect(
+                &ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert!((hit.point - na::Point3::new(0.0, 0.0, -4.0)).norm() < 1e-5);
+        assert!((hit.normal - na::Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn sphere_miss() {
+        let r = ray([0.0, 2.0, 0.0], [0.0, 0.0, -1.0]);
+        assert!(sphere().intersect(&r, T_MIN, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn sphere_tangent_ray_hits_once() {
+        let hit = sphere()
+            .intersect(
+                &ray([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-3);
+        assert!((hit.point - na::Point3::new(0.0, 1.0, -5.0)).norm() < 1e-3);
+    }
 
-    let view_port_height: f32 = 2.0;
+    #[test]
+    fn sphere_behind_ray_is_missed() {
+        let r = ray([0.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        assert!(sphere().intersect(&r, T_MIN, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn sphere_hit_from_inside_is_far_side() {
+        let hit = sphere()
+            .intersect(
+                &ray([0.0, 0.0, -5.0], [1.0, 0.0, 0.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(!hit.front_face);
+        assert!((hit.normal - na::Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-5);
+    }
 
-    let objects: Vec<Box<dyn Object + Sync>> = vec![
-        Box::new(Sphere {
-            centre: na::Point3::new(0.0, -100.5, -1.0),
-            radius: 100f32,
+    #[test]
+    fn sphere_hit_beyond_t_max_is_missed() {
+        let r = ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]);
+        assert!(sphere().intersect(&r, T_MIN, 3.0).is_none());
+    }
+
+    #[test]
+    fn scattered_rays_do_not_hit_their_own_start() {
+        let sphere = sphere();
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(6);
+        let mut acne = 0;
+        for i in 0..1000 {
+            let r = ray(
+                [(i % 30) as f32 * 0.02, (i / 30) as f32 * 0.02, 0.0],
+                [0.0, 0.0, -1.0],
+            );
+            let hit = sphere.intersect(&r, T_MIN, f32::INFINITY).unwrap();
+            // Leaving the outside of a sphere, nothing lies ahead but rounding
+            // errors at the start point, which T_MIN skips.
+            let scattered = scatter(&mut rng, r, &hit, DiffuseMode::UnitVector).unwrap();
+            assert!(sphere.intersect(&scattered, T_MIN, f32::INFINITY).is_none());
+            if sphere.intersect(&scattered, 0.0, f32::INFINITY).is_some() {
+                acne += 1;
+            }
+        }
+        // Without the margin, some of them would have hit.
+        assert!(acne > 0);
+    }
+
+    #[test]
+    fn one_sided_light_is_dark_from_behind() {
+        // A ceiling light facing down, seen from below and from above.
+        let light = rect::XZRect {
+            x0: -1.0,
+            x1: 1.0,
+            z0: -1.0,
+            z1: 1.0,
+            k: 1.0,
+            flip: true,
             material: Material {
-                material_type: MaterialType::Lambertian,
-                color: color::Color::new(0.8f32, 0.8f32, 0.0),
+                material_type: MaterialType::Emissive,
+                texture: texture::Texture::Solid(color::Color::new(4.0, 4.0, 4.0)),
+                two_sided: false,
+                normal_map: None,
             },
-        }),
-        Box::new(Sphere {
-            centre: na::Point3::new(0.0, 0.0, -1.0),
-            radius: 0.5f32,
+        };
+        let below = ray([0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        let above = ray([0.0, 2.0, 0.0], [0.0, -1.0, 0.0]);
+        let lit = light.intersect(&below, T_MIN, f32::INFINITY).unwrap();
+        assert_eq!(
+            lit.emitted(image_texture::Filter::Bilinear),
+            color::Color::new(4.0, 4.0, 4.0)
+        );
+        let dark = light.intersect(&above, T_MIN, f32::INFINITY).unwrap();
+        assert_eq!(
+            dark.emitted(image_texture::Filter::Bilinear),
+            color::Color::new(0.0, 0.0, 0.0)
+        );
+
+        let two_sided = rect::XZRect {
             material: Material {
-                material_type: MaterialType::Lambertian,
-                color: color::Color::new(0.7f32, 0.3f32, 0.3f32),
+                two_sided: true,
+                ..light.material
             },
-        }),
-        Box::new(Sphere {
-            centre: na::Point3::new(-1.0, 0.0, -1.0),
-            radius: 0.5f32,
+            ..light
+        };
+        let hit = two_sided.intersect(&above, T_MIN, f32::INFINITY).unwrap();
+        assert_eq!(
+            hit.emitted(image_texture::Filter::Bilinear),
+            color::Color::new(4.0, 4.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn oren_nayar_keeps_light_at_grazing_angles() {
+        let hit_with = |material_type, origin: [f32; 3], direction: [f32; 3]| {
+            let sphere = Sphere {
+                material: Material {
+                    material_type,
+                    ..sphere().material
+                },
+                ..sphere()
+            };
+            sphere
+                .intersect(&ray(origin, direction), T_MIN, f32::INFINITY)
+                .unwrap()
+        };
+        let head_on = |material_type| hit_with(material_type, [0.0, 0.0, 0.0], [0.0, 0.0, -1.0]);
+        let to_light = na::Vector3::new(0.0, 0.0, 1.0);
+        assert_eq!(
+            head_on(MaterialType::Lambertian).diffuse_weight(to_light),
+            1.0
+        );
+        assert_eq!(
+            head_on(MaterialType::OrenNayar(0.0)).diffuse_weight(to_light),
+            1.0
+        );
+        // Lit and seen head on a rough surface is darker, since many facets
+        // are tilted away...
+        let rough = head_on(MaterialType::OrenNayar(0.5));
+        assert!(rough.diffuse_weight(to_light) < 1.0);
+        // ...but near the edge, with the light behind the viewer, it is much
+        // brighter.
+        let edge = hit_with(
+            MaterialType::OrenNayar(0.5),
+            [0.0, 0.95, 0.0],
+            [0.0, 0.0, -1.0],
+        );
+        let weight = edge.diffuse_weight(-edge.incoming);
+        assert!(weight > 1.5, "weight {}", weight);
+    }
+
+    #[test]
+    fn fresnel_metal_whitens_at_grazing_angles() {
+        let gold = Material {
+            material_type: MaterialType::FresnelMetal(0.0),
+            texture: texture::Texture::Solid(color::Color::new(1.0, 0.8, 0.3)),
+            two_sided: true,
+            normal_map: None,
+        };
+        let sphere = Sphere {
+            material: gold,
+            ..sphere()
+        };
+        let head_on = sphere
+            .intersect(
+                &ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert_eq!(
+            head_on.attenuation(
+                na::Vector3::new(0.0, 0.0, 1.0),
+                image_texture::Filter::Bilinear
+            ),
+            color::Color::new(1.0, 0.8, 0.3)
+        );
+        let grazing = sphere
+            .intersect(
+                &ray([0.0, 0.999, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            )
+            .unwrap();
+        let c = grazing.attenuation(
+            reflect(grazing.incoming, grazing.normal),
+            image_texture::Filter::Bilinear,
+        );
+        assert!(c.max_channel() <= 1.0);
+        assert!(c.distance_squared(color::Color::new(1.0, 1.0, 1.0)) < 0.03);
+    }
+
+    // Averages the light carried on by many bounces off a white PBR surface
+    // lit from everywhere, which cannot be more than came in.
+    fn pbr_albedo(metallic: f32, roughness: f32, origin: [f32; 3]) -> f32 {
+        let sphere = Sphere {
             material: Material {
-                material_type: MaterialType::Metal(0.15),
-                color: color::Color::new(0.8f32, 0.8f32, 0.8f32),
+                material_type: MaterialType::Pbr {
+                    metallic,
+                    roughness,
+                },
+                texture: texture::Texture::Solid(color::Color::new(1.0, 1.0, 1.0)),
+                two_sided: true,
+                normal_map: None,
             },
-        }),
-        Box::new(Sphere {
-            centre: na::Point3::new(1.0, 0.0, -1.0),
-            radius: 0.5f32,
+            ..sphere()
+        };
+        let r = ray(origin, [0.0, 0.0, -1.0]);
+        let hit = sphere.intersect(&r, T_MIN, f32::INFINITY).unwrap();
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(9);
+        let n = 10_000;
+        let total: f32 = (0..n)
+            .filter_map(|_| scatter(&mut rng, r, &hit, DiffuseMode::UnitVector))
+            .map(|scattered| {
+                hit.attenuation(scattered.direction, image_texture::Filter::Bilinear)
+                    .max_channel()
+            })
+            .sum();
+        total / n as f32
+    }
+
+    #[test]
+    fn pbr_keeps_energy_and_smooth_metal_mirrors() {
+        for &metallic in &[0.0, 1.0] {
+            for &roughness in &[0.0, 0.5, 1.0] {
+                for origin in [[0.0, 0.0, 0.0], [0.0, 0.7, 0.0], [0.0, 0.97, 0.0]] {
+                    let albedo = pbr_albedo(metallic, roughness, origin);
+                    assert!(
+                        albedo.is_finite() && albedo < 1.02,
+                        "metallic {} roughness {} from {:?}: {}",
+                        metallic,
+                        roughness,
+                        origin,
+                        albedo
+                    );
+                }
+            }
+        }
+        // A smooth white metal reflects everything, as a mirror does.
+        assert!(pbr_albedo(1.0, 0.0, [0.0, 0.7, 0.0]) > 0.97);
+        let sphere = Sphere {
             material: Material {
-                material_type: MaterialType::Metal(0.0),
-                color: color::Color::new(0.8f32, 0.6f32, 0.2f32),
+                material_type: MaterialType::Pbr {
+                    metallic: 1.0,
+                    roughness: 0.0,
+                },
+                ..sphere().material
             },
-        }),
-    ];
+            ..sphere()
+        };
+        let r = ray([0.0, 0.5, 0.0], [0.0, 0.0, -1.0]);
+        let hit = sphere.intersect(&r, T_MIN, f32::INFINITY).unwrap();
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+        let mirror = reflect(r.direction, hit.normal);
+        // GGX has long tails, even when it is this narrow.
+        let spread: f32 = (0..100)
+            .map(|_| {
+                let scattered = scatter(&mut rng, r, &hit, DiffuseMode::UnitVector).unwrap();
+                scattered.direction.angle(&mirror)
+            })
+            .sum();
+        assert!(spread / 100.0 < 0.01);
+    }
+
+    #[test]
+    fn normal_map_bends_along_uv_directions() {
+        let r = ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]);
+        let mapped = |red: f32, green: f32, blue: f32| Sphere {
+            material: Material {
+                normal_map: Some(texture::Texture::Solid(color::Color::new(red, green, blue))),
+                ..sphere().material
+            },
+            ..sphere()
+        };
+        let normal = |object: &dyn Object, r: &ray::Ray| {
+            let hit = object.intersect(r, T_MIN, f32::INFINITY).unwrap();
+            hit.with_normal_map(image_texture::Filter::Bilinear).normal
+        };
+        let bent = |red, green, blue| normal(&mapped(red, green, blue), &r);
+        // Facing the ray, u increases along +x and v along +y.
+        let lean = 0.5 + 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!((bent(0.5, 0.5, 1.0) - na::Vector3::z()).norm() < 1e-5);
+        let along_u = na::Vector3::new(1.0, 0.0, 1.0).normalize();
+        assert!((bent(lean, 0.5, lean) - along_u).norm() < 1e-4);
+        let along_v = na::Vector3::new(0.0, 1.0, 1.0).normalize();
+        assert!((bent(0.5, lean, lean) - along_v).norm() < 1e-4);
+        // Bent all the way round to face away, the normal is left alone.
+        assert!((bent(0.5, 0.5, 0.0) - na::Vector3::z()).norm() < 1e-5);
+        // Turned to x = -5, the sphere bends its normal the same way in its
+        // own frame.
+        let turned = instance::RotateY::new(Box::new(mapped(lean, 0.5, lean)), 90.0);
+        let sideways = normal(&turned, &ray([0.0, 0.0, 0.0], [-1.0, 0.0, 0.0]));
+        assert!((sideways - na::Vector3::new(1.0, 0.0, -1.0).normalize()).norm() < 1e-4);
+    }
 
-    raytracing(
-        aspect_ratio,
-        height,
-        view_port_height,
-        &objects,
-        "05_spheres_pic.ppm",
-    );
+    #[test]
+    fn diffuse_modes_spread_bounces_differently() {
+        let r = ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]);
+        let hit = sphere().intersect(&r, T_MIN, f32::INFINITY).unwrap();
+        let mean_cosine = |mode| {
+            let mut rng = rand::rngs::SmallRng::seed_from_u64(4);
+            let n = 20_000;
+            let total: f32 = (0..n)
+                .map(|_| {
+                    let scattered = scatter(&mut rng, r, &hit, mode).unwrap();
+                    scattered.direction.normalize().dot(&hit.normal)
+                })
+                .sum();
+            total / n as f32
+        };
+        // Cosine-weighted directions average a cosine of 2/3, uniform ones 1/2.
+        assert!((mean_cosine(DiffuseMode::UnitVector) - 2.0 / 3.0).abs() < 0.01);
+        assert!((mean_cosine(DiffuseMode::Cosine) - 2.0 / 3.0).abs() < 0.01);
+        assert!((mean_cosine(DiffuseMode::UniformHemisphere) - 0.5).abs() < 0.01);
+    }
 
-    let mat1 = MaterialType::Lambertian;
-    let mat2 = MaterialType::Metal(0.5);
-    println!("mat1 = {}", to_string(&mat1)?);
-    println!("mat2 = {}", to_string(&mat2)?);
+    fn settings(width: u32, height: u32, samples_per_pixel: u32) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            max_depth: cli::DEFAULT_MAX_DEPTH,
+            dither: false,
+            tone_map: false,
+            gamma: cli::DEFAULT_GAMMA,
+            exposure: color::Color::new(1.0, 1.0, 1.0),
+            mode: RenderMode::Shaded,
+            sampler: Sampler::Random,
+            diffuse: DiffuseMode::UnitVector,
+            texture_filter: image_texture::Filter::Bilinear,
+            roulette_depth: None,
+            min_bounces: 0,
+            preview: false,
+            quiet: true,
+            binary_ppm: false,
+            transparent: false,
+            adaptive: None,
+            firefly_clamp: None,
+            bloom: None,
+            denoise: None,
+            edges: None,
+            wireframe: None,
+            checkpoint: None,
+            resume: false,
+            sample_map: false,
+            seed: 1,
+        }
+    }
 
-    Ok(())
+    // A small light straight ahead of the camera, against a grey sky.
+    fn light_ahead() -> (bvh::World, background::Background, camera::Camera) {
+        let light = rect::XYRect {
+            x0: -0.3,
+            x1: 0.3,
+            y0: -0.3,
+            y1: 0.3,
+            k: -2.0,
+            flip: false,
+            material: Material {
+                material_type: MaterialType::Emissive,
+                texture: texture::Texture::Solid(color::Color::new(0.25, 1.0, 4.0)),
+                two_sided: true,
+                normal_map: None,
+            },
+        };
+        let world = bvh::World::new(vec![primitive::Primitive::XYRect(light)]);
+        let background = background::Background::Solid(color::Color::new(0.04, 0.04, 0.04));
+        let camera = camera::Camera::new(
+            na::Point3::origin(),
+            na::Point3::new(0.0, 0.0, -1.0),
+            na::Vector3::y(),
+            40.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        (world, background, camera)
+    }
+
+    fn render_light_ahead(settings: &RenderSettings) -> Image {
+        let (world, background, camera) = light_ahead();
+        render_to_buffer("test", &camera, settings, None, |r, rng| {
+            path_color(r, rng, settings, &world, &[], &[], &background)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn render_to_buffer_sees_light_in_centre() {
+        let image = render_light_ahead(&settings(9, 9, 4));
+        assert_eq!((image.width, image.height, image.pixels.len()), (9, 9, 81));
+        assert!(image.alpha.is_none());
+        let shown = |c: color::Color| {
+            let mut c = c;
+            c.gamma_correction(cli::DEFAULT_GAMMA);
+            c.clamp();
+            c.to_rgb8()
+        };
+        assert_eq!(
+            image.pixels[4 * 9 + 4],
+            shown(color::Color::new(0.25, 1.0, 4.0))
+        );
+        assert_eq!(image.pixels[0], shown(color::Color::new(0.04, 0.04, 0.04)));
+    }
+
+    #[test]
+    fn adaptive_stops_early_on_transparent_background() {
+        let settings = RenderSettings {
+            transparent: true,
+            adaptive: Some(Adaptive {
+                threshold: 0.01,
+                min_samples: 4,
+            }),
+            ..settings(9, 9, 64)
+        };
+        let image = render_light_ahead(&settings);
+        // The corner only sees the background, and the centre only the light.
+        assert_eq!(image.samples[0], 4);
+        assert_eq!(image.samples[4 * 9 + 4], 4);
+        assert_eq!(image.alpha.unwrap()[0], 0);
+    }
+
+    #[test]
+    fn multi_jittered_renders_repeat() {
+        let settings = RenderSettings {
+            sampler: Sampler::MultiJittered,
+            ..settings(16, 16, 8)
+        };
+        assert_eq!(
+            render_light_ahead(&settings).pixels,
+            render_light_ahead(&settings).pixels
+        );
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_sampler_noise() {
+        let (width, height) = (64, 64);
+        let reference = render_light_ahead(&settings(width, height, 4096)).pixels;
+        for sampler in [Sampler::Random, Sampler::Stratified, Sampler::MultiJittered] {
+            let mut total = 0.0;
+            let seeds = 8;
+            for seed in 0..seeds {
+                let settings = RenderSettings {
+                    sampler,
+                    seed,
+                    ..settings(width, height, 64)
+                };
+                let pixels = render_light_ahead(&settings).pixels;
+                let squared: f32 = pixels
+                    .iter()
+                    .zip(&reference)
+                    .flat_map(|(p, r)| {
+                        p.iter()
+                            .zip(r)
+                            .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
+                    })
+                    .sum();
+                total += (squared / (3 * pixels.len()) as f32).sqrt();
+            }
+            println!("{:?}: RMS error {:.3}", sampler, total / seeds as f32);
+        }
+    }
+
+    #[test]
+    fn ppm_writes_to_any_writer() {
+        let pixels = [[0, 0, 0], [255, 128, 1]];
+        let mut plain = Vec::new();
+        write_ppm(&mut plain, 2, 1, &pixels).unwrap();
+        assert_eq!(plain, b"P3\n2 1\n255\n0 0 0\n255 128 1\n");
+        let mut binary = Vec::new();
+        write_ppm_binary(&mut binary, 2, 1, &pixels).unwrap();
+        assert_eq!(binary, b"P6\n2 1\n255\n\x00\x00\x00\xff\x80\x01");
+    }
+
+    #[test]
+    fn checkpoint_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("raytracer-test-{}.checkpoint", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut pixel = PixelState::default();
+        for c in &[0.1, 1.0 / 3.0, 7.25] {
+            pixel.color.add(color::Color::new(*c, 0.5, 1e-7));
+            pixel.taken += 1;
+        }
+        pixel.hits = 2;
+        let checkpoint = Checkpoint {
+            width: 2,
+            height: 1,
+            seed: u64::MAX,
+            samples: 3,
+            pixels: vec![pixel, PixelState::default()],
+        };
+        checkpoint.save(path).unwrap();
+        let loaded = Checkpoint::load(path, 2, 1).unwrap().unwrap();
+        assert_eq!((loaded.seed, loaded.samples), (u64::MAX, 3));
+        assert_eq!(loaded.pixels[0].color.mean(), pixel.color.mean());
+        assert_eq!(
+            loaded.pixels[0].color.standard_error(),
+            pixel.color.standard_error()
+        );
+        assert_eq!((loaded.pixels[0].hits, loaded.pixels[0].taken), (2, 3));
+        assert!(Checkpoint::load(path, 1, 2).is_err());
+        std::fs::remove_file(path).unwrap();
+        assert!(Checkpoint::load(path, 2, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn resumed_render_matches_one_run() {
+        let output =
+            std::env::temp_dir().join(format!("raytracer-test-resume-{}.png", std::process::id()));
+        let output = output.to_str().unwrap();
+        let (world, background, camera) = light_ahead();
+        let render = |settings: &RenderSettings| {
+            render_to_buffer(output, &camera, settings, None, |r, rng| {
+                path_color(r, rng, settings, &world, &[], &[], &background)
+            })
+        };
+        let checkpointed = |samples_per_pixel, resume| RenderSettings {
+            checkpoint: Some(1),
+            resume,
+            ..settings(9, 9, samples_per_pixel)
+        };
+        // Resuming needs a checkpoint, and starting over must not replace one.
+        assert!(render(&checkpointed(2, true)).is_err());
+        render(&checkpointed(2, false)).unwrap();
+        assert!(render(&checkpointed(3, false)).is_err());
+        let resumed = render(&checkpointed(3, true)).unwrap();
+        std::fs::remove_file(Checkpoint::path(output)).unwrap();
+        let single = render(&settings(9, 9, 5)).unwrap();
+        assert_eq!(resumed.samples, vec![5; 81]);
+        assert_eq!(resumed.pixels, single.pixels);
+    }
+
+    #[test]
+    fn resuming_does_not_resample_edges() {
+        let output = std::env::temp_dir().join(format!(
+            "raytracer-test-resume-edges-{}.png",
+            std::process::id()
+        ));
+        let output = output.to_str().unwrap();
+        let (world, background, camera) = light_ahead();
+        let render = |resume| {
+            let settings = RenderSettings {
+                checkpoint: Some(1),
+                resume,
+                edges: Some(edges::Edges {
+                    threshold: 0.1,
+                    samples: 8,
+                }),
+                ..settings(9, 9, 2)
+            };
+            render_to_buffer(output, &camera, &settings, None, |r, rng| {
+                path_color(r, rng, &settings, &world, &[], &[], &background)
+            })
+            .unwrap()
+        };
+        let first = render(false);
+        let resumed = render(true);
+        std::fs::remove_file(Checkpoint::path(output)).unwrap();
+        // The edge pixels already had their 8, and the others gain 2, or
+        // reach 8 if they only stand out once they have 4.
+        for (&before, &after) in first.samples.iter().zip(&resumed.samples) {
+            match before {
+                8 => assert_eq!(after, 8),
+                _ => assert!(before == 2 && (after == 4 || after == 8)),
+            }
+        }
+        assert!(first.samples.contains(&8));
+    }
+
+    #[test]
+    fn frame_path_numbers_before_extension() {
+        assert_eq!(frame_path("frame.ppm", 1), "frame_0001.ppm");
+        assert_eq!(
+            frame_path("out/turntable.png", 12),
+            "out/turntable_0012.png"
+        );
+        assert_eq!(frame_path("frame", 3), "frame_0003");
+    }
+
+    #[test]
+    fn heat_map_scales_to_most_samples() {
+        let map = sample_heat_map(&[0, 10, 20, 30, 15]);
+        assert_eq!(
+            map[..4],
+            [[0, 0, 0], [255, 0, 0], [255, 255, 0], [255, 255, 255]]
+        );
+        assert_eq!(map[4], [255, 127, 0]);
+        assert_eq!(sample_heat_map(&[0, 0]), vec![[0, 0, 0]; 2]);
+    }
+
+    #[test]
+    fn suffixed_path_keeps_extension() {
+        assert_eq!(
+            suffixed_path("out/render.png", "albedo"),
+            "out/render_albedo.png"
+        );
+        assert_eq!(suffixed_path("render", "normal"), "render_normal");
+    }
+
+    #[test]
+    fn sphere_light_samples_its_cone() {
+        let light = sphere();
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(5);
+        let origin = na::Point3::origin();
+        // Every sample lands on the sphere, at the density of its cone.
+        let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - (24.0f32 / 25.0).sqrt());
+        for _ in 0..1000 {
+            let direction = light.random(origin, &mut rng);
+            assert!((light.pdf_value(origin, direction) * solid_angle - 1.0).abs() < 1e-3);
+        }
+        // Over all directions the density integrates to one.
+        let n = 200_000;
+        let total: f32 = (0..n)
+            .map(|_| light.pdf_value(origin, random_unit_vector(&mut rng)))
+            .sum();
+        let integral = total * 4.0 * std::f32::consts::PI / n as f32;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+    }
+
+    #[test]
+    fn random_unit_vector_is_unit_length() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(7);
+        for _ in 0..10_000 {
+            assert!((random_unit_vector(&mut rng).norm() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn metal_fuzz_is_clamped_and_absorbs_below_surface() {
+        // A grazing hit, where fuzzed reflections often dip below the surface.
+        let r = ray([0.0, 0.95, 0.0], [0.0, 0.0, -1.0]);
+        let directions = |fuzz: f32| {
+            let sphere = Sphere {
+                material: Material {
+                    material_type: MaterialType::Metal(fuzz),
+                    ..sphere().material
+                },
+                ..sphere()
+            };
+            let hit = sphere.intersect(&r, T_MIN, f32::INFINITY).unwrap();
+            let mut rng = rand::rngs::SmallRng::seed_from_u64(8);
+            let scattered: Vec<_> = (0..1000)
+                .map(|_| scatter(&mut rng, r, &hit, DiffuseMode::UnitVector).map(|s| s.direction))
+                .collect();
+            (hit.normal, scattered)
+        };
+        let (normal, clamped) = directions(5.0);
+        assert_eq!(clamped, directions(1.0).1);
+        assert!(clamped.iter().any(|d| d.is_none()));
+        assert!(clamped.iter().flatten().all(|d| d.dot(&normal) > 0.0));
+    }
+
+    #[test]
+    fn lambertian_direction_falls_back_to_normal() {
+        let normal = na::Vector3::new(1.0, 2.0, -2.0).normalize();
+        let direction = lambertian_direction(-normal, normal);
+        assert!(direction.iter().all(|c| c.is_finite()));
+        assert_eq!(direction, normal);
+    }
 }
@@ -0,0 +1,95 @@
+use rand::{Rng, SeedableRng};
+
+use crate::aabb;
+use crate::color;
+use crate::ray;
+use crate::texture::Texture;
+use crate::{HitRecord, Material, MaterialType, Object};
+
+// Fog or smoke of uniform `density` filling `boundary`, which must be closed
+// and convex. A ray passing through scatters after an exponentially
+// distributed distance, in a random direction, tinted by `phase_color`.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Object + Sync>,
+    pub density: f32,
+    pub phase_color: color::Color,
+}
+
+impl Object for ConstantMedium {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // Where the ray's line enters and leaves the boundary, even if that is
+        // behind the ray's origin.
+        let entry = self
+            .boundary
+            .intersect(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self
+            .boundary
+            .intersect(ray, entry.t + 1e-4, f32::INFINITY)?;
+
+        let t0 = entry.t.max(t_min).max(0.0);
+        let t1 = exit.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+        // The ray's own seed, so that rays cast by different samples scatter
+        // at different depths but a render still only depends on its seed.
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(ray.seed);
+        let distance = -(1.0 - rng.gen::<f32>()).ln() / self.density;
+        if distance > t1 - t0 {
+            return None;
+        }
+
+        // The medium has no surface, so any normal will do; facing the ray
+        // keeps `front_face` set.
+        Some(HitRecord::new(
+            ray,
+            t0 + distance,
+            -ray.direction,
+            (0.0, 0.0),
+            Material {
+                material_type: MaterialType::Isotropic,
+                texture: Texture::Solid(self.phase_color),
+                two_sided: true,
+                normal_map: None,
+            },
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::testing::{grey, ray};
+    use crate::T_MIN;
+    use nalgebra as na;
+
+    #[test]
+    fn rays_pass_through_with_exponential_falloff() {
+        let density = 0.7;
+        let fog = ConstantMedium {
+            boundary: Box::new(Cube::new(
+                na::Point3::new(0.0, 0.0, 0.0),
+                na::Point3::new(1.0, 1.0, 1.0),
+                grey(),
+            )),
+            density,
+            phase_color: color::Color::new(1.0, 1.0, 1.0),
+        };
+        // The same ray every time, told apart only by the seeds its samples
+        // give it.
+        let n = 20_000;
+        let passed = (0..n)
+            .filter(|&seed| {
+                let r = ray([-1.0, 0.5, 0.5], [1.0, 0.0, 0.0]).with_seed(seed);
+                fog.intersect(&r, T_MIN, f32::INFINITY).is_none()
+            })
+            .count();
+        let expected = (-density * 1.0f32).exp();
+        assert!((passed as f32 / n as f32 - expected).abs() < 0.01);
+    }
+}
@@ -0,0 +1,45 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{HitRecord, Material, Object, Sphere};
+
+// A sphere whose centre moves in a straight line from `centre0` at `time0` to
+// `centre1` at `time1`. It stays put at either end outside that interval.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct MovingSphere {
+    pub centre0: na::Point3<f32>,
+    pub centre1: na::Point3<f32>,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl MovingSphere {
+    fn at_time(&self, time: f32) -> Sphere {
+        let s = if self.time1 > self.time0 {
+            ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Sphere {
+            centre: self.centre0 + s * (self.centre1 - self.centre0),
+            radius: self.radius,
+            material: self.material,
+        }
+    }
+}
+
+impl Object for MovingSphere {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.at_time(ray.time).intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let start = self.at_time(self.time0).bounding_box()?;
+        let end = self.at_time(self.time1).bounding_box()?;
+        Some(aabb::Aabb::surrounding_box(start, end))
+    }
+}
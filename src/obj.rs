@@ -0,0 +1,255 @@
+use std::fs;
+use std::io;
+
+use nalgebra as na;
+
+use crate::bvh::World;
+use crate::primitive::Primitive;
+use crate::triangle::Triangle;
+use crate::{Material, Object};
+
+fn invalid_data(line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line_no, msg),
+    )
+}
+
+// Resolves a 1-based or negative (counting back from the end) index into a
+// list of `count` items, for the reference `token` in an error message.
+fn resolve_index(index: &str, count: usize, token: &str, line_no: usize) -> io::Result<usize> {
+    let index: i64 = index
+        .parse()
+        .map_err(|_| invalid_data(line_no, &format!("invalid vertex reference `{}`", token)))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid_data(
+            line_no,
+            &format!("vertex reference `{}` out of range", token),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+// Resolves a face vertex reference like `3`, `3/1`, `3//2` or `-1` into an
+// index into the vertices and, if it names one, into the normals. Texture
+// indices are ignored.
+fn vertex_index(
+    token: &str,
+    num_vertices: usize,
+    num_normals: usize,
+    line_no: usize,
+) -> io::Result<(usize, Option<usize>)> {
+    let mut parts = token.split('/');
+    let vertex = resolve_index(parts.next().unwrap_or(""), num_vertices, token, line_no)?;
+    let normal = match parts.nth(1) {
+        Some(normal) if !normal.is_empty() => {
+            Some(resolve_index(normal, num_normals, token, line_no)?)
+        }
+        _ => None,
+    };
+    Ok((vertex, normal))
+}
+
+// Reads the three numbers after a `v` or `vn`, naming the `kind` of line in
+// errors.
+fn coordinates<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    kind: &str,
+    line_no: usize,
+) -> io::Result<na::Vector3<f32>> {
+    let coords = tokens
+        .take(3)
+        .map(|t| t.parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|_| invalid_data(line_no, &format!("invalid {} coordinate", kind)))?;
+    if coords.len() != 3 {
+        return Err(invalid_data(
+            line_no,
+            &format!("{} needs three coordinates", kind),
+        ));
+    }
+    Ok(na::Vector3::new(coords[0], coords[1], coords[2]))
+}
+
+// Loads the faces of a Wavefront OBJ file as triangles, triangulating polygons
+// with more than three vertices as a fan. Faces whose vertices all have
+// normals (`vn`) are shaded smoothly with them.
+pub fn load_obj(path: &str, material: Material) -> io::Result<Vec<Triangle>> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut vertices: Vec<na::Point3<f32>> = Vec::new();
+    let mut normals: Vec<na::Vector3<f32>> = Vec::new();
+    let mut triangles = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => vertices.push(coordinates(tokens, "vertex", line_no)?.into()),
+            Some("vn") => normals.push(coordinates(tokens, "normal", line_no)?),
+            Some("f") => {
+                let face = tokens
+                    .map(|t| vertex_index(t, vertices.len(), normals.len(), line_no))
+                    .collect::<io::Result<Vec<_>>>()?;
+                if face.len() < 3 {
+                    return Err(invalid_data(line_no, "face needs at least three vertices"));
+                }
+                for k in 1..face.len() - 1 {
+                    let corners = [face[0], face[k], face[k + 1]];
+                    let triangle = Triangle::new(
+                        vertices[corners[0].0],
+                        vertices[corners[1].0],
+                        vertices[corners[2].0],
+                        material,
+                    );
+                    triangles.push(match corners.map(|(_, n)| n) {
+                        [Some(a), Some(b), Some(c)] => {
+                            triangle.with_normals([normals[a], normals[b], normals[c]])
+                        }
+                        _ => triangle,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+// Loads the triangles of an OBJ file into their own BVH, to use the whole
+// mesh as one object.
+pub fn load_obj_as_bvh(path: &str, material: Material) -> io::Result<Box<dyn Object + Sync>> {
+    let triangles = load_obj(path, material)?;
+    if triangles.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no faces", path),
+        ));
+    }
+    Ok(Box::new(World::new(
+        triangles.into_iter().map(Primitive::Triangle).collect(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::grey;
+    use crate::{ray, T_MIN};
+    use rand::{Rng, SeedableRng};
+    use std::io::Write;
+
+    // Writes a unit sphere of `rings` by `segments` quads, each split into
+    // two triangles by the loader, to a temporary OBJ file.
+    fn write_sphere(name: &str, rings: usize, segments: usize) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.obj", name, std::process::id()));
+        let mut out = io::BufWriter::new(fs::File::create(&path).unwrap());
+        for ring in 0..=rings {
+            let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+            for segment in 0..segments {
+                let phi = 2.0 * std::f32::consts::PI * segment as f32 / segments as f32;
+                let (x, y, z) = (
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                writeln!(out, "v {} {} {}", x, y, z).unwrap();
+            }
+        }
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let at = |r: usize, s: usize| r * segments + s % segments + 1;
+                let (a, b) = (at(ring, segment), at(ring, segment + 1));
+                let (c, d) = (at(ring + 1, segment + 1), at(ring + 1, segment));
+                writeln!(out, "f {} {} {} {}", a, b, c, d).unwrap();
+            }
+        }
+        out.flush().unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    // Rays from all around the sphere towards points near its middle.
+    fn rays(count: usize) -> Vec<ray::Ray> {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(2);
+        (0..count)
+            .map(|_| {
+                let from = na::Point3::new(rng.gen_range(-3.0, 3.0), rng.gen_range(-3.0, 3.0), 3.0);
+                let to = na::Point3::new(rng.gen_range(-1.2, 1.2), rng.gen_range(-1.2, 1.2), 0.0);
+                ray::Ray::new(from, to - from, 0.0)
+            })
+            .collect()
+    }
+
+    fn nearest<M: Object + ?Sized>(mesh: &M, rays: &[ray::Ray]) -> Vec<Option<f32>> {
+        rays.iter()
+            .map(|r| mesh.intersect(r, T_MIN, f32::INFINITY).map(|hit| hit.t))
+            .collect()
+    }
+
+    #[test]
+    fn bvh_finds_the_same_hits_as_every_triangle() {
+        let path = write_sphere("raytracer-test-mesh", 8, 16);
+        let flat: Vec<Triangle> = load_obj(&path, grey()).unwrap();
+        let bvh = load_obj_as_bvh(&path, grey()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(flat.len(), 2 * 8 * 16);
+        let rays = rays(1000);
+        assert_eq!(nearest(&flat[..], &rays), nearest(bvh.as_ref(), &rays));
+    }
+
+    #[test]
+    fn faces_take_their_vertex_normals() {
+        let path =
+            std::env::temp_dir().join(format!("raytracer-test-normals-{}.obj", std::process::id()));
+        fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vn 0 0 1\nvn 1 0 0\nvn 0 1 0\n\
+             f 1//1 2//2 3//3 4//2\nf 1 2 3\nf 1//1 2 3//3\n",
+        )
+        .unwrap();
+        let triangles = load_obj(path.to_str().unwrap(), grey()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let (x, y, z) = (na::Vector3::x(), na::Vector3::y(), na::Vector3::z());
+        let normals: Vec<_> = triangles.iter().map(|t| t.normals).collect();
+        // The quad is a fan of two triangles, both from its first corner.
+        assert_eq!(normals, vec![Some([z, x, y]), Some([z, y, x]), None, None]);
+    }
+
+    #[test]
+    fn bundled_sphere_has_no_degenerate_triangles() {
+        let triangles = load_obj("models/sphere.obj", grey()).unwrap();
+        assert_eq!(triangles.len(), 2 * 24 + 2 * 10 * 24);
+        for t in &triangles {
+            let [a, b, c] = t.vertices;
+            let normal = (b - a).cross(&(c - a));
+            assert!(normal.norm() > 1e-6);
+            // Wound so that the face normal points out, as the vertex normals do.
+            assert!(normal.dot(&t.normals.unwrap()[0]) > 0.0);
+        }
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_mesh_with_and_without_bvh() {
+        let path = write_sphere("raytracer-bench-mesh", 50, 100);
+        let flat: Vec<Triangle> = load_obj(&path, grey()).unwrap();
+        let bvh = load_obj_as_bvh(&path, grey()).unwrap();
+        fs::remove_file(&path).unwrap();
+        println!("{} triangles", flat.len());
+        let rays = rays(2000);
+        let start = std::time::Instant::now();
+        let flat_hits = nearest(&flat[..], &rays);
+        println!("flat: {:?}", start.elapsed());
+        let start = std::time::Instant::now();
+        let bvh_hits = nearest(bvh.as_ref(), &rays);
+        println!("bvh: {:?}", start.elapsed());
+        assert_eq!(flat_hits, bvh_hits);
+    }
+}
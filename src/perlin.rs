@@ -0,0 +1,97 @@
+use nalgebra as na;
+
+// Ken Perlin's reference permutation of 0..=255, used to hash lattice points.
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(i: i64) -> usize {
+    PERMUTATION[i.rem_euclid(256) as usize] as usize
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// Dot product of the offset (x, y, z) with one of twelve gradient directions
+// picked by the low bits of `hash`.
+fn grad(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// Improved Perlin noise, roughly in [-1, 1] and zero at every lattice point.
+pub fn noise(p: na::Point3<f32>) -> f32 {
+    let cell = p.coords.map(|c| c.floor());
+    let [xi, yi, zi] = [cell[0] as i64, cell[1] as i64, cell[2] as i64];
+    let (x, y, z) = (p[0] - cell[0], p[1] - cell[1], p[2] - cell[2]);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = hash(xi) + yi.rem_euclid(256) as usize;
+    let aa = hash(a as i64) + zi.rem_euclid(256) as usize;
+    let ab = hash(a as i64 + 1) + zi.rem_euclid(256) as usize;
+    let b = hash(xi + 1) + yi.rem_euclid(256) as usize;
+    let ba = hash(b as i64) + zi.rem_euclid(256) as usize;
+    let bb = hash(b as i64 + 1) + zi.rem_euclid(256) as usize;
+
+    let corner = |h: usize, dx: f32, dy: f32, dz: f32| grad(hash(h as i64), x - dx, y - dy, z - dz);
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(aa, 0.0, 0.0, 0.0), corner(ba, 1.0, 0.0, 0.0)),
+            lerp(u, corner(ab, 0.0, 1.0, 0.0), corner(bb, 1.0, 1.0, 0.0)),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                corner(aa + 1, 0.0, 0.0, 1.0),
+                corner(ba + 1, 1.0, 0.0, 1.0),
+            ),
+            lerp(
+                u,
+                corner(ab + 1, 0.0, 1.0, 1.0),
+                corner(bb + 1, 1.0, 1.0, 1.0),
+            ),
+        ),
+    )
+}
+
+// Sum of `octaves` layers of noise, each at twice the frequency and half the
+// weight of the last, folded to be non-negative.
+pub fn turbulence(p: na::Point3<f32>, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut weight = 1.0;
+    let mut pt = p;
+    for _ in 0..octaves {
+        sum += weight * noise(pt);
+        weight *= 0.5;
+        pt = na::Point3::from(2.0 * pt.coords);
+    }
+    sum.abs()
+}
@@ -0,0 +1,38 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{HitRecord, Material, Object};
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Plane {
+    pub point: na::Point3<f32>,
+    pub normal: na::Vector3<f32>,
+    pub material: Material,
+}
+
+impl Object for Plane {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction);
+        // A ray parallel to the plane never hits it.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.point - ray.orig).dot(&self.normal) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        Some(HitRecord::new(
+            ray,
+            t,
+            self.normal.normalize(),
+            (0.0, 0.0),
+            self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        None
+    }
+}
@@ -0,0 +1,138 @@
+use crate::aabb;
+use crate::cone::Cone;
+use crate::cylinder::Cylinder;
+use crate::disk::Disk;
+use crate::moving_sphere::MovingSphere;
+use crate::plane::Plane;
+use crate::ray;
+use crate::rect::{XYRect, XZRect, YZRect};
+use crate::triangle::Triangle;
+use crate::{HitRecord, Object, Sphere};
+
+// The objects a scene is built from, stored by value so that intersecting
+// them is a `match` the compiler can inline instead of a call through a
+// vtable. Anything else still works through `Other`.
+pub enum Primitive {
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Plane(Plane),
+    Triangle(Triangle),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    XYRect(XYRect),
+    XZRect(XZRect),
+    YZRect(YZRect),
+    Other(Box<dyn Object + Sync>),
+}
+
+impl Object for Primitive {
+    #[inline]
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        match self {
+            Primitive::Sphere(o) => o.intersect(ray, t_min, t_max),
+            Primitive::MovingSphere(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Plane(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Triangle(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Cylinder(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Cone(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Disk(o) => o.intersect(ray, t_min, t_max),
+            Primitive::XYRect(o) => o.intersect(ray, t_min, t_max),
+            Primitive::XZRect(o) => o.intersect(ray, t_min, t_max),
+            Primitive::YZRect(o) => o.intersect(ray, t_min, t_max),
+            Primitive::Other(o) => o.intersect(ray, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        match self {
+            Primitive::Sphere(o) => o.bounding_box(),
+            Primitive::MovingSphere(o) => o.bounding_box(),
+            Primitive::Plane(o) => o.bounding_box(),
+            Primitive::Triangle(o) => o.bounding_box(),
+            Primitive::Cylinder(o) => o.bounding_box(),
+            Primitive::Cone(o) => o.bounding_box(),
+            Primitive::Disk(o) => o.bounding_box(),
+            Primitive::XYRect(o) => o.bounding_box(),
+            Primitive::XZRect(o) => o.bounding_box(),
+            Primitive::YZRect(o) => o.bounding_box(),
+            Primitive::Other(o) => o.bounding_box(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::World;
+    use crate::scene::Scene;
+    use crate::{nearest_intersection, scatter, DiffuseMode, T_MIN};
+    use rand::{Rng, SeedableRng};
+
+    // The same object behind a trait object, as every object was stored
+    // before `Primitive` existed.
+    fn boxed(primitive: Primitive) -> Primitive {
+        match primitive {
+            Primitive::Sphere(o) => Primitive::Other(Box::new(o)),
+            Primitive::MovingSphere(o) => Primitive::Other(Box::new(o)),
+            Primitive::Plane(o) => Primitive::Other(Box::new(o)),
+            Primitive::Triangle(o) => Primitive::Other(Box::new(o)),
+            Primitive::Cylinder(o) => Primitive::Other(Box::new(o)),
+            Primitive::Cone(o) => Primitive::Other(Box::new(o)),
+            Primitive::Disk(o) => Primitive::Other(Box::new(o)),
+            Primitive::XYRect(o) => Primitive::Other(Box::new(o)),
+            Primitive::XZRect(o) => Primitive::Other(Box::new(o)),
+            Primitive::YZRect(o) => Primitive::Other(Box::new(o)),
+            Primitive::Other(o) => Primitive::Other(o),
+        }
+    }
+
+    // Follows 500 paths through each pixel of a small image and counts the
+    // surfaces they hit.
+    fn trace(scene: &Scene, world: &World) -> usize {
+        let (width, height) = (64, 36);
+        let camera = scene.camera(width as f32 / height as f32, 0.0);
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+        let mut hits = 0;
+        for y in 0..height {
+            for x in 0..width {
+                for _ in 0..500 {
+                    let u = (x as f32 + rng.gen::<f32>()) / (width - 1) as f32;
+                    let v = (y as f32 + rng.gen::<f32>()) / (height - 1) as f32;
+                    let mut ray = camera.get_ray(u, v, &mut rng);
+                    for _ in 0..20 {
+                        let hit = match nearest_intersection(&ray, world, T_MIN, f32::INFINITY) {
+                            Some(hit) => hit,
+                            None => break,
+                        };
+                        hits += 1;
+                        match scatter(&mut rng, ray, &hit, DiffuseMode::UnitVector) {
+                            Some(scattered) => ray = scattered,
+                            None => break,
+                        }
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_enum_against_boxed() {
+        let scene = Scene::load("scenes/05_spheres.json").unwrap();
+        let by_value = World::new(scene.objects().unwrap());
+        let by_box = World::new(scene.objects().unwrap().into_iter().map(boxed).collect());
+
+        let mut counts = Vec::new();
+        // Warm up, so that the first timed run isn't penalised.
+        trace(&scene, &by_value);
+        for (name, world) in [("enum", &by_value), ("boxed", &by_box)] {
+            let start = std::time::Instant::now();
+            counts.push(trace(&scene, world));
+            println!("{}: {:?}", name, start.elapsed());
+        }
+        assert_eq!(counts[0], counts[1]);
+    }
+}
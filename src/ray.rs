@@ -4,16 +4,48 @@ use nalgebra::geometry as ng;
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub orig: na::Point3<f32>,
-    pub direction: na::Vector3<f32>
+    pub direction: na::Vector3<f32>,
+    // When the ray was cast, for objects that move while the shutter is open.
+    pub time: f32,
+    // Drawn from the RNG of the sample that cast the ray, for objects that
+    // decide at random whether the ray hits them, like fog.
+    pub seed: u64
 }
 
 impl Ray {
-    pub fn new(origin: na::Point3<f32>, dir: na::Vector3<f32>) -> Ray {
+    pub fn new(origin: na::Point3<f32>, dir: na::Vector3<f32>, time: f32) -> Ray {
         let unit_dir = dir.normalize();
-        Ray { orig: origin, direction: unit_dir }
+        Ray { orig: origin, direction: unit_dir, time, seed: 0 }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Ray {
+        self.seed = seed;
+        self
     }
 
     pub fn at(&self, length: f32) -> na::Point3<f32> {
         ng::Translation3::from(length * self.direction) * self.orig
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_normalizes_direction() {
+        let ray = Ray::new(na::Point3::new(1.0, 2.0, 3.0), na::Vector3::new(3.0, 0.0, 4.0), 0.5);
+        assert!((ray.direction.norm() - 1.0).abs() < 1e-6);
+        assert!((ray.direction - na::Vector3::new(0.6, 0.0, 0.8)).norm() < 1e-6);
+        assert_eq!(ray.orig, na::Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(ray.time, 0.5);
+    }
+
+    #[test]
+    fn at_moves_along_direction() {
+        let ray = Ray::new(na::Point3::new(1.0, 0.0, -1.0), na::Vector3::new(0.0, 2.0, 0.0), 0.0);
+        for &(length, y) in &[(0.0, 0.0), (1.0, 1.0), (2.5, 2.5), (-3.0, -3.0)] {
+            assert!((ray.at(length) - na::Point3::new(1.0, y, -1.0)).norm() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use nalgebra as na;
+use rand::Rng;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{HitRecord, Material, Object, Sampleable, T_MIN};
+
+// A rectangle at `k` along axis `axes[2]` spanning [a0, a1] along `axes[0]` and
+// [b0, b1] along `axes[1]`. The three public rectangles are thin wrappers
+// around this so they share one intersection routine.
+struct AxisRect {
+    axes: [usize; 3],
+    a0: f32,
+    a1: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    flip: bool,
+    material: Material,
+}
+
+impl AxisRect {
+    // The extents may be given either way round.
+    fn new(
+        axes: [usize; 3],
+        (a0, a1): (f32, f32),
+        (b0, b1): (f32, f32),
+        k: f32,
+        flip: bool,
+        material: Material,
+    ) -> AxisRect {
+        AxisRect {
+            axes,
+            a0: a0.min(a1),
+            a1: a0.max(a1),
+            b0: b0.min(b1),
+            b1: b0.max(b1),
+            k,
+            flip,
+            material,
+        }
+    }
+
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [a, b, c] = self.axes;
+        // A ray parallel to the rectangle never hits it.
+        if ray.direction[c].abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.k - ray.orig[c]) / ray.direction[c];
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let pt = ray.at(t);
+        if pt[a] < self.a0 || pt[a] > self.a1 || pt[b] < self.b0 || pt[b] > self.b1 {
+            return None;
+        }
+        let mut normal = na::Vector3::zeros();
+        normal[c] = if self.flip { -1.0 } else { 1.0 };
+        let (mut dpdu, mut dpdv) = (na::Vector3::zeros(), na::Vector3::zeros());
+        dpdu[a] = 1.0;
+        dpdv[b] = 1.0;
+        Some(
+            HitRecord::new(
+                ray,
+                t,
+                normal,
+                (
+                    (pt[a] - self.a0) / (self.a1 - self.a0),
+                    (pt[b] - self.b0) / (self.b1 - self.b0),
+                ),
+                self.material,
+            )
+            .with_tangents(dpdu, dpdv),
+        )
+    }
+
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        let [a, b, c] = self.axes;
+        let mut pt = na::Point3::origin();
+        pt[a] = rng.gen_range(self.a0, self.a1);
+        pt[b] = rng.gen_range(self.b0, self.b1);
+        pt[c] = self.k;
+        pt - origin
+    }
+
+    // A uniformly chosen point has density 1 / area; seen from `origin` that
+    // becomes distance^2 / (cos * area) per unit solid angle.
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        let ray = ray::Ray::new(origin, direction, 0.0);
+        match self.intersect(&ray, T_MIN, f32::INFINITY) {
+            Some(hit) => {
+                let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+                let cosine = ray.direction[self.axes[2]].abs();
+                hit.t.powi(2) / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    // The box is flat along the fixed axis, which the inclusive slab test in
+    // `Aabb::hit` handles.
+    fn bounding_box(&self) -> aabb::Aabb {
+        let [a, b, c] = self.axes;
+        let mut min = na::Point3::origin();
+        let mut max = na::Point3::origin();
+        min[a] = self.a0;
+        max[a] = self.a1;
+        min[b] = self.b0;
+        max[b] = self.b1;
+        min[c] = self.k;
+        max[c] = self.k;
+        aabb::Aabb::new(min, max)
+    }
+}
+
+// Rectangle in the plane z = k, facing +z, or -z if `flip` is set.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct XYRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub y0: f32,
+    pub y1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip: bool,
+    pub material: Material,
+}
+
+impl XYRect {
+    fn rect(&self) -> AxisRect {
+        AxisRect::new(
+            [0, 1, 2],
+            (self.x0, self.x1),
+            (self.y0, self.y1),
+            self.k,
+            self.flip,
+            self.material,
+        )
+    }
+}
+
+impl Object for XYRect {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.rect().intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+impl Sampleable for XYRect {
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        self.rect().random(origin, rng)
+    }
+
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        self.rect().pdf_value(origin, direction)
+    }
+}
+
+// Rectangle in the plane y = k, facing +y, or -y if `flip` is set.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct XZRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip: bool,
+    pub material: Material,
+}
+
+impl XZRect {
+    fn rect(&self) -> AxisRect {
+        AxisRect::new(
+            [0, 2, 1],
+            (self.x0, self.x1),
+            (self.z0, self.z1),
+            self.k,
+            self.flip,
+            self.material,
+        )
+    }
+}
+
+impl Object for XZRect {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.rect().intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+impl Sampleable for XZRect {
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        self.rect().random(origin, rng)
+    }
+
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        self.rect().pdf_value(origin, direction)
+    }
+}
+
+// Rectangle in the plane x = k, facing +x, or -x if `flip` is set.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct YZRect {
+    pub y0: f32,
+    pub y1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip: bool,
+    pub material: Material,
+}
+
+impl YZRect {
+    fn rect(&self) -> AxisRect {
+        AxisRect::new(
+            [1, 2, 0],
+            (self.y0, self.y1),
+            (self.z0, self.z1),
+            self.k,
+            self.flip,
+            self.material,
+        )
+    }
+}
+
+impl Object for YZRect {
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.rect().intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        Some(self.rect().bounding_box())
+    }
+}
+
+impl Sampleable for YZRect {
+    fn random(&self, origin: na::Point3<f32>, rng: &mut rand::rngs::SmallRng) -> na::Vector3<f32> {
+        self.rect().random(origin, rng)
+    }
+
+    fn pdf_value(&self, origin: na::Point3<f32>, direction: na::Vector3<f32>) -> f32 {
+        self.rect().pdf_value(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{grey, ray};
+    use rand::SeedableRng;
+
+    fn rect(x0: f32, x1: f32) -> XYRect {
+        XYRect {
+            x0,
+            x1,
+            y0: 1.0,
+            y1: -1.0,
+            k: -2.0,
+            flip: false,
+            material: grey(),
+        }
+    }
+
+    #[test]
+    fn reversed_extents_are_the_same_rect() {
+        let (forward, reversed) = (rect(-1.0, 1.0), rect(1.0, -1.0));
+        let hit = |r: &XYRect| {
+            let hit = r.intersect(
+                &ray([0.5, 0.5, 0.0], [0.0, 0.0, -1.0]),
+                T_MIN,
+                f32::INFINITY,
+            );
+            hit.map(|hit| (hit.t, hit.uv))
+        };
+        assert_eq!(hit(&reversed), Some((2.0, (0.75, 0.75))));
+        assert_eq!(hit(&reversed), hit(&forward));
+        let bbox = reversed.bounding_box().unwrap();
+        assert_eq!(
+            (bbox.min, bbox.max),
+            (
+                na::Point3::new(-1.0, -1.0, -2.0),
+                na::Point3::new(1.0, 1.0, -2.0)
+            )
+        );
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+        let towards = reversed.random(na::Point3::origin(), &mut rng);
+        assert!(towards.x.abs() <= 1.0 && towards.y.abs() <= 1.0 && towards.z == -2.0);
+    }
+}
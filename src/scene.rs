@@ -0,0 +1,337 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::background::Background;
+use crate::bvh;
+use crate::camera;
+use crate::color;
+use crate::cone::Cone;
+use crate::cube::Cube;
+use crate::cylinder::Cylinder;
+use crate::disk::Disk;
+use crate::instance::{RotateY, Translate};
+use crate::light::Light;
+use crate::medium::ConstantMedium;
+use crate::moving_sphere::MovingSphere;
+use crate::obj;
+use crate::plane::Plane;
+use crate::primitive::Primitive;
+use crate::rect::{XYRect, XZRect, YZRect};
+use crate::triangle::Triangle;
+use crate::{Material, MaterialType, Object, Sampleable, Sphere};
+
+pub const DEFAULT_WIDTH: u32 = 455;
+pub const DEFAULT_HEIGHT: u32 = 256;
+pub const DEFAULT_SAMPLES_PER_PIXEL: u32 = 500;
+pub const DEFAULT_OUTPUT: &str = "image.ppm";
+
+fn default_width() -> u32 {
+    DEFAULT_WIDTH
+}
+
+fn default_height() -> u32 {
+    DEFAULT_HEIGHT
+}
+
+fn default_samples_per_pixel() -> u32 {
+    DEFAULT_SAMPLES_PER_PIXEL
+}
+
+fn default_output() -> String {
+    DEFAULT_OUTPUT.to_string()
+}
+
+fn default_frames() -> u32 {
+    1
+}
+
+fn default_vfov() -> f32 {
+    90.0
+}
+
+fn default_vup() -> na::Vector3<f32> {
+    na::Vector3::y()
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct CameraConfig {
+    pub look_from: na::Point3<f32>,
+    pub look_at: na::Point3<f32>,
+    #[serde(default = "default_vup")]
+    pub vup: na::Vector3<f32>,
+    // Vertical field of view in degrees. Ignored by an orthographic camera.
+    #[serde(default = "default_vfov")]
+    pub vfov: f32,
+    // A 35 mm equivalent focal length in millimetres, which sets the field of
+    // view instead of `vfov` when given.
+    #[serde(default)]
+    pub focal_length: Option<f32>,
+    // When set, use a parallel projection with a viewport this many units
+    // tall instead of a perspective one.
+    #[serde(default)]
+    pub orthographic_height: Option<f32>,
+    #[serde(default)]
+    pub aperture: f32,
+    // Defaults to the distance between `look_from` and `look_at`.
+    #[serde(default)]
+    pub focus_dist: Option<f32>,
+    // The shutter is open from `shutter_open` to `shutter_close`; both
+    // default to 0, which disables motion blur.
+    #[serde(default)]
+    pub shutter_open: f32,
+    #[serde(default)]
+    pub shutter_close: f32,
+    // Degrees that `look_from` turns about `vup` through `look_at` over the
+    // course of an animation, for a turntable.
+    #[serde(default)]
+    pub orbit: f32,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum SceneObject {
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Plane(Plane),
+    Triangle(Triangle),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    XYRect(XYRect),
+    XZRect(XZRect),
+    YZRect(YZRect),
+    Mesh {
+        path: String,
+        material: Material,
+    },
+    // An axis-aligned box between two opposite corners.
+    Cube {
+        p0: na::Point3<f32>,
+        p1: na::Point3<f32>,
+        material: Material,
+    },
+    // Wrappers placing another object (or a whole mesh) in the scene.
+    Translate {
+        offset: na::Vector3<f32>,
+        object: Box<SceneObject>,
+    },
+    // `angle` is in degrees about the y axis.
+    RotateY {
+        angle: f32,
+        object: Box<SceneObject>,
+    },
+    // Fog or smoke filling a closed, convex `boundary`.
+    ConstantMedium {
+        boundary: Box<SceneObject>,
+        density: f32,
+        phase_color: color::Color,
+    },
+}
+
+// A rectangle with no width or height can't be hit, and as a light it has
+// no points to pick from.
+fn check_rect((a0, a1): (f32, f32), (b0, b1): (f32, f32)) -> io::Result<()> {
+    if (a1 - a0).abs() > 0.0 && (b1 - b0).abs() > 0.0 {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "a rectangle from {} to {} by {} to {} has no area",
+            a0, a1, b0, b1
+        ),
+    ))
+}
+
+impl SceneObject {
+    fn build(&self, objects: &mut Vec<Primitive>) -> io::Result<()> {
+        match self {
+            SceneObject::Sphere(sphere) => objects.push(Primitive::Sphere(*sphere)),
+            SceneObject::MovingSphere(sphere) => objects.push(Primitive::MovingSphere(*sphere)),
+            SceneObject::Plane(plane) => objects.push(Primitive::Plane(*plane)),
+            SceneObject::Triangle(triangle) => objects.push(Primitive::Triangle(*triangle)),
+            SceneObject::Cylinder(cylinder) => objects.push(Primitive::Cylinder(*cylinder)),
+            SceneObject::Cone(cone) => objects.push(Primitive::Cone(*cone)),
+            SceneObject::Disk(disk) => objects.push(Primitive::Disk(*disk)),
+            SceneObject::XYRect(rect) => {
+                check_rect((rect.x0, rect.x1), (rect.y0, rect.y1))?;
+                objects.push(Primitive::XYRect(*rect))
+            }
+            SceneObject::XZRect(rect) => {
+                check_rect((rect.x0, rect.x1), (rect.z0, rect.z1))?;
+                objects.push(Primitive::XZRect(*rect))
+            }
+            SceneObject::YZRect(rect) => {
+                check_rect((rect.y0, rect.y1), (rect.z0, rect.z1))?;
+                objects.push(Primitive::YZRect(*rect))
+            }
+            SceneObject::Mesh { path, material } => objects.extend(
+                obj::load_obj(path, *material)?
+                    .into_iter()
+                    .map(Primitive::Triangle),
+            ),
+            SceneObject::Cube { p0, p1, material } => {
+                objects.push(Primitive::Other(Box::new(Cube::new(*p0, *p1, *material))))
+            }
+            SceneObject::Translate { offset, object } => {
+                objects.push(Primitive::Other(Box::new(Translate {
+                    object: object.build_one()?,
+                    offset: *offset,
+                })))
+            }
+            SceneObject::RotateY { angle, object } => objects.push(Primitive::Other(Box::new(
+                RotateY::new(object.build_one()?, *angle),
+            ))),
+            SceneObject::ConstantMedium {
+                boundary,
+                density,
+                phase_color,
+            } => objects.push(Primitive::Other(Box::new(ConstantMedium {
+                boundary: boundary.build_one()?,
+                density: *density,
+                phase_color: *phase_color,
+            }))),
+        }
+        Ok(())
+    }
+
+    // Builds this object as a single `Object`, putting the triangles of a mesh
+    // in their own BVH so a wrapper can treat them as one.
+    fn build_one(&self) -> io::Result<Box<dyn Object + Sync>> {
+        if let SceneObject::Mesh { path, material } = self {
+            return obj::load_obj_as_bvh(path, *material);
+        }
+        let mut objects = Vec::new();
+        self.build(&mut objects)?;
+        if objects.len() == 1 {
+            Ok(Box::new(objects.pop().unwrap()))
+        } else {
+            Ok(Box::new(bvh::World::new(objects)))
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Scene {
+    #[serde(default = "default_width")]
+    pub width: u32,
+    #[serde(default = "default_height")]
+    pub height: u32,
+    #[serde(default = "default_samples_per_pixel")]
+    pub samples_per_pixel: u32,
+    pub camera: CameraConfig,
+    pub objects: Vec<SceneObject>,
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default = "default_output")]
+    pub output: String,
+    #[serde(default)]
+    pub dither: bool,
+    #[serde(default)]
+    pub tone_map: bool,
+    // Number of animation frames, spread evenly over times [0, 1).
+    #[serde(default = "default_frames")]
+    pub frames: u32,
+}
+
+impl Scene {
+    pub fn load(path: &str) -> io::Result<Scene> {
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Moves the camera to frame `bbox`, keeping the direction it looks from
+    // and its field of view, and focuses it on the centre of the box. The
+    // camera is left alone if there is nothing to frame.
+    pub fn fit_camera(
+        &mut self,
+        bbox: &aabb::Aabb,
+        aspect_ratio: f32,
+        margin: f32,
+    ) -> Result<(), String> {
+        let c = &mut self.camera;
+        let vfov = c
+            .focal_length
+            .map_or(c.vfov, camera::vfov_from_focal_length);
+        let (look_from, look_at) =
+            camera::fit(bbox, c.look_from - c.look_at, vfov, aspect_ratio, margin)?;
+        if let Some(height) = &mut c.orthographic_height {
+            let diameter = (1.0 + margin) * (bbox.max - bbox.min).norm();
+            *height = diameter * (1.0 / aspect_ratio).max(1.0);
+        }
+        c.look_from = look_from;
+        c.look_at = look_at;
+        c.focus_dist = None;
+        Ok(())
+    }
+
+    // The camera for the animation frame at `time`, which also shifts the
+    // shutter so that moving objects carry on from frame to frame.
+    pub fn camera(&self, aspect_ratio: f32, time: f32) -> camera::Camera {
+        let c = &self.camera;
+        let orbit = na::Rotation3::from_axis_angle(
+            &na::Unit::new_normalize(c.vup),
+            (c.orbit * time).to_radians(),
+        );
+        let look_from = c.look_at + orbit * (c.look_from - c.look_at);
+        let camera = match c.orthographic_height {
+            Some(height) => {
+                camera::Camera::orthographic(look_from, c.look_at, c.vup, height, aspect_ratio)
+            }
+            None => camera::Camera::new(
+                look_from,
+                c.look_at,
+                c.vup,
+                c.focal_length
+                    .map_or(c.vfov, camera::vfov_from_focal_length),
+                aspect_ratio,
+                c.aperture,
+                c.focus_dist
+                    .unwrap_or_else(|| (c.look_at - c.look_from).norm()),
+            ),
+        };
+        camera.with_shutter(c.shutter_open + time, c.shutter_close + time)
+    }
+
+    // Emissive spheres, rectangles and disks at the top level of the scene,
+    // which the renderer samples directly. Lights inside wrappers are only
+    // found by chance.
+    pub fn area_lights(&self) -> Vec<Box<dyn Sampleable + Sync>> {
+        let emissive = |m: &Material| matches!(m.material_type, MaterialType::Emissive);
+        let mut lights: Vec<Box<dyn Sampleable + Sync>> = Vec::new();
+        for object in &self.objects {
+            match object {
+                SceneObject::Sphere(sphere) if emissive(&sphere.material) => {
+                    lights.push(Box::new(*sphere))
+                }
+                SceneObject::XYRect(rect) if emissive(&rect.material) => {
+                    lights.push(Box::new(*rect))
+                }
+                SceneObject::XZRect(rect) if emissive(&rect.material) => {
+                    lights.push(Box::new(*rect))
+                }
+                SceneObject::YZRect(rect) if emissive(&rect.material) => {
+                    lights.push(Box::new(*rect))
+                }
+                SceneObject::Disk(disk) if emissive(&disk.material) => lights.push(Box::new(*disk)),
+                _ => {}
+            }
+        }
+        lights
+    }
+
+    pub fn objects(&self) -> io::Result<Vec<Primitive>> {
+        let mut objects = Vec::new();
+        for object in &self.objects {
+            object.build(&mut objects)?;
+        }
+        Ok(objects)
+    }
+}
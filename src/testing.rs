@@ -0,0 +1,20 @@
+// Fixtures shared by the tests of several modules.
+
+use nalgebra as na;
+
+use crate::texture::Texture;
+use crate::{color, ray, Material, MaterialType};
+
+// A plain grey diffuse surface, for tests that only care about geometry.
+pub fn grey() -> Material {
+    Material {
+        material_type: MaterialType::Lambertian,
+        texture: Texture::Solid(color::Color::new(0.5, 0.5, 0.5)),
+        two_sided: true,
+        normal_map: None,
+    }
+}
+
+pub fn ray(origin: [f32; 3], direction: [f32; 3]) -> ray::Ray {
+    ray::Ray::new(na::Point3::from(origin), na::Vector3::from(direction), 0.0)
+}
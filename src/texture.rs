@@ -0,0 +1,61 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::color;
+use crate::image_texture::{Filter, ImageTexture};
+use crate::perlin;
+
+fn white() -> color::Color {
+    color::Color::new(1.0, 1.0, 1.0)
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub enum Texture {
+    Solid(color::Color),
+    // A 3D checkerboard made of cubes with sides of length `scale`.
+    Checker {
+        even: color::Color,
+        odd: color::Color,
+        scale: f32,
+    },
+    // Smooth Perlin noise with features about `scale` units across, shading
+    // `color` from black to full.
+    Noise {
+        scale: f32,
+        #[serde(default = "white")]
+        color: color::Color,
+    },
+    // An image mapped by the surface's (u, v) coordinates.
+    Image(ImageTexture),
+    // Marble veins: sine bands along z, `scale` units apart over a full
+    // period of 2 pi, distorted by turbulent noise.
+    Marble {
+        scale: f32,
+        #[serde(default = "white")]
+        color: color::Color,
+    },
+}
+
+impl Texture {
+    pub fn value(&self, uv: (f32, f32), pt: na::Point3<f32>, filter: Filter) -> color::Color {
+        match *self {
+            Texture::Solid(color) => color,
+            Texture::Checker { even, odd, scale } => {
+                let cell = (pt.coords / scale).map(|c| c.floor() as i64);
+                if (cell[0] + cell[1] + cell[2]).rem_euclid(2) == 0 {
+                    even
+                } else {
+                    odd
+                }
+            }
+            Texture::Noise { scale, color } => {
+                let p = na::Point3::from(pt.coords / scale);
+                0.5 * (1.0 + perlin::noise(p)) * color
+            }
+            Texture::Image(image) => image.value(uv, filter),
+            Texture::Marble { scale, color } => {
+                0.5 * (1.0 + (pt[2] / scale + 10.0 * perlin::turbulence(pt, 7)).sin()) * color
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use nalgebra as na;
+use serde_derive::*;
+
+use crate::aabb;
+use crate::ray;
+use crate::{HitRecord, Material, Object};
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Triangle {
+    pub vertices: [na::Point3<f32>; 3],
+    // Normals at the vertices, interpolated across the triangle so that a
+    // mesh of a curved surface shades smoothly. Without them the triangle is
+    // shaded flat.
+    #[serde(default)]
+    pub normals: Option<[na::Vector3<f32>; 3]>,
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(
+        a: na::Point3<f32>,
+        b: na::Point3<f32>,
+        c: na::Point3<f32>,
+        material: Material,
+    ) -> Triangle {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [na::Vector3<f32>; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    fn edges(&self) -> (na::Vector3<f32>, na::Vector3<f32>) {
+        (
+            self.vertices[1] - self.vertices[0],
+            self.vertices[2] - self.vertices[0],
+        )
+    }
+}
+
+impl Object for Triangle {
+    // Möller–Trumbore ray-triangle intersection.
+    fn intersect(&self, ray: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (edge1, edge2) = self.edges();
+        let pvec = ray.direction.cross(&edge2);
+        let det = edge1.dot(&pvec);
+        // The ray is parallel to the triangle, or the triangle has no area.
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.orig - self.vertices[0];
+        let u = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let v = ray.direction.dot(&qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        // The barycentric coordinates double as texture coordinates.
+        let face_normal = edge1.cross(&edge2).normalize();
+        let mut hit = HitRecord::new(ray, t, face_normal, (u, v), self.material);
+        if let Some([n0, n1, n2]) = self.normals {
+            let shading = (1.0 - u - v) * n0 + u * n1 + v * n2;
+            // Vertex normals that are zero or cancel out have no direction to
+            // give, so the face's own is kept.
+            if shading.norm_squared() > 1e-12 {
+                // Which side is the front still follows the face, so the
+                // shading normal is turned to the face's side before facing
+                // the ray.
+                let mut shading = shading.normalize();
+                if shading.dot(&face_normal) < 0.0 {
+                    shading = -shading;
+                }
+                hit.normal = if hit.front_face { shading } else { -shading };
+            }
+        }
+        Some(hit.with_tangents(edge1, edge2))
+    }
+
+    fn bounding_box(&self) -> Option<aabb::Aabb> {
+        let [a, b, c] = self.vertices;
+        Some(aabb::Aabb::new(a.inf(&b).inf(&c), a.sup(&b).sup(&c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::grey;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            na::Point3::new(0.0, 0.0, 0.0),
+            na::Point3::new(1.0, 0.0, 0.0),
+            na::Point3::new(0.0, 1.0, 0.0),
+            grey(),
+        )
+    }
+
+    fn hit_at(triangle: &Triangle, x: f32, y: f32, from_z: f32) -> HitRecord {
+        let ray = ray::Ray::new(
+            na::Point3::new(x, y, from_z),
+            na::Vector3::new(0.0, 0.0, -from_z),
+            0.0,
+        );
+        triangle.intersect(&ray, 1e-3, f32::INFINITY).unwrap()
+    }
+
+    #[test]
+    fn flat_without_normals() {
+        let hit = hit_at(&triangle(), 0.2, 0.2, 1.0);
+        assert_eq!(hit.normal, na::Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn zero_area_triangle_is_never_hit() {
+        let sliver = Triangle::new(
+            na::Point3::new(0.0, 0.0, 0.0),
+            na::Point3::new(1.0, 1.0, 0.0),
+            na::Point3::new(2.0, 2.0, 0.0),
+            grey(),
+        );
+        for &(x, y) in &[(1.0, 1.0), (0.5, 0.5), (1.0, 0.9)] {
+            let ray = ray::Ray::new(
+                na::Point3::new(x, y, 1.0),
+                na::Vector3::new(0.0, 0.0, -1.0),
+                0.0,
+            );
+            assert!(sliver.intersect(&ray, 1e-3, f32::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn interpolates_vertex_normals() {
+        let lean = |x: f32| na::Vector3::new(x, 0.0, 1.0).normalize();
+        let smooth = triangle().with_normals([lean(-1.0), lean(1.0), lean(-1.0)]);
+        // At the first and second vertices the normal is theirs; halfway
+        // between them the tilts cancel.
+        assert!((hit_at(&smooth, 0.0, 0.0, 1.0).normal - lean(-1.0)).norm() < 1e-5);
+        assert!((hit_at(&smooth, 1.0, 0.0, 1.0).normal - lean(1.0)).norm() < 1e-5);
+        let middle = hit_at(&smooth, 0.5, 0.0, 1.0);
+        assert!((middle.normal - na::Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+        // From behind, the normal still faces the ray.
+        let behind = hit_at(&smooth, 0.0, 0.0, -1.0);
+        assert!(!behind.front_face);
+        assert!((behind.normal + lean(-1.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn falls_back_to_face_normal_without_a_direction() {
+        let z = na::Vector3::z();
+        let cancelling = triangle().with_normals([z, -z, z]);
+        let zero = triangle().with_normals([na::Vector3::zeros(); 3]);
+        for triangle in &[cancelling, zero] {
+            assert_eq!(hit_at(triangle, 0.5, 0.0, 1.0).normal, z);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use nalgebra as na;
+
+use crate::aabb::Aabb;
+use crate::bvh::World;
+use crate::camera::Camera;
+
+// Which boxes a wireframe overlay outlines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wireframe {
+    // The bounding box of every object that has one.
+    Objects,
+    // The boxes of the BVH's inner nodes.
+    Bvh,
+}
+
+impl std::str::FromStr for Wireframe {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Wireframe, ()> {
+        match s {
+            "objects" => Ok(Wireframe::Objects),
+            "bvh" => Ok(Wireframe::Bvh),
+            _ => Err(()),
+        }
+    }
+}
+
+// Edges closer to the camera than this are cut off, since points at or
+// behind it have nowhere to go on screen.
+const NEAR: f32 = 1e-4;
+
+// The edges of boxes drawn over a finished image, one pixel wide, as seen
+// through the camera that rendered it.
+pub struct Overlay {
+    camera: Camera,
+    boxes: Vec<Aabb>,
+    color: [u8; 3],
+}
+
+impl Overlay {
+    pub fn new(wireframe: Wireframe, camera: &Camera, world: &World) -> Overlay {
+        let (branches, color) = match wireframe {
+            Wireframe::Objects => (false, [0, 255, 0]),
+            Wireframe::Bvh => (true, [255, 0, 255]),
+        };
+        Overlay {
+            camera: *camera,
+            boxes: world.boxes(branches),
+            color,
+        }
+    }
+
+    pub fn draw(&self, pixels: &mut [[u8; 3]], width: u32, height: u32) {
+        for bbox in &self.boxes {
+            let corner = |i: usize| {
+                let pick = |bit: usize, axis: usize| {
+                    if i & bit == 0 {
+                        bbox.min[axis]
+                    } else {
+                        bbox.max[axis]
+                    }
+                };
+                na::Point3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+            };
+            // The twelve edges join corners that differ along one axis.
+            for i in 0..8 {
+                for bit in [1, 2, 4] {
+                    if i & bit == 0 {
+                        self.draw_edge(pixels, width, height, corner(i), corner(i | bit));
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_edge(
+        &self,
+        pixels: &mut [[u8; 3]],
+        width: u32,
+        height: u32,
+        a: na::Point3<f32>,
+        b: na::Point3<f32>,
+    ) {
+        let (depth_a, depth_b) = (self.camera.depth(a), self.camera.depth(b));
+        if depth_a < NEAR && depth_b < NEAR {
+            return;
+        }
+        // Cut the part behind the camera off the edge.
+        let cut = |from: na::Point3<f32>, depth_from: f32, to: na::Point3<f32>, depth_to: f32| {
+            if depth_from < NEAR {
+                from + (to - from) * ((NEAR - depth_from) / (depth_to - depth_from))
+            } else {
+                from
+            }
+        };
+        let (a, b) = (cut(a, depth_a, b, depth_b), cut(b, depth_b, a, depth_a));
+
+        // Pixel (column, row) is centred on viewport coordinates
+        // ((column + 0.5) / (width - 1), (row + 0.5) / (height - 1)), with
+        // rows counted up from the bottom.
+        let to_pixel = |p: na::Point3<f32>| {
+            let (s, t) = self.camera.project(p);
+            let row = t * (height - 1) as f32 - 0.5;
+            na::Point2::new(s * (width - 1) as f32 - 0.5, (height - 1) as f32 - row)
+        };
+        let corner = na::Point2::new(width as f32 - 0.5, height as f32 - 0.5);
+        let (start, end) = match clip(
+            to_pixel(a),
+            to_pixel(b),
+            na::Point2::new(-0.5, -0.5),
+            corner,
+        ) {
+            Some(segment) => segment,
+            None => return,
+        };
+
+        let span = end - start;
+        let steps = span.amax().ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let p = start + span * (step as f32 / steps as f32);
+            let (x, y) = (p.x.round(), p.y.round());
+            if x >= 0.0 && y >= 0.0 && x < width as f32 && y < height as f32 {
+                pixels[y as usize * width as usize + x as usize] = self.color;
+            }
+        }
+    }
+}
+
+// The part of the segment from `a` to `b` inside the rectangle from `min` to
+// `max`, by Liang-Barsky clipping.
+fn clip(
+    a: na::Point2<f32>,
+    b: na::Point2<f32>,
+    min: na::Point2<f32>,
+    max: na::Point2<f32>,
+) -> Option<(na::Point2<f32>, na::Point2<f32>)> {
+    let d = b - a;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for axis in 0..2 {
+        for (p, q) in [
+            (-d[axis], a[axis] - min[axis]),
+            (d[axis], max[axis] - a[axis]),
+        ] {
+            if p == 0.0 {
+                // Parallel to this side, and entirely outside it.
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some((a + t0 * d, a + t1 * d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_to_rectangle() {
+        let (min, max) = (na::Point2::new(0.0, 0.0), na::Point2::new(10.0, 5.0));
+        let (a, b) = clip(
+            na::Point2::new(-5.0, 2.0),
+            na::Point2::new(15.0, 2.0),
+            min,
+            max,
+        )
+        .unwrap();
+        assert_eq!(
+            (a, b),
+            (na::Point2::new(0.0, 2.0), na::Point2::new(10.0, 2.0))
+        );
+        let inside = (na::Point2::new(1.0, 1.0), na::Point2::new(2.0, 3.0));
+        assert_eq!(clip(inside.0, inside.1, min, max), Some(inside));
+        assert!(clip(
+            na::Point2::new(-1.0, 6.0),
+            na::Point2::new(11.0, 6.0),
+            min,
+            max
+        )
+        .is_none());
+        assert!(clip(
+            na::Point2::new(-3.0, 1.0),
+            na::Point2::new(1.0, 9.0),
+            min,
+            max
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn outlines_box_in_front_and_skips_box_behind() {
+        let camera = Camera::new(
+            na::Point3::new(0.0, 0.0, 3.0),
+            na::Point3::origin(),
+            na::Vector3::y(),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let (width, height) = (33, 33);
+        let draw = |bbox: Aabb| {
+            let overlay = Overlay {
+                camera,
+                boxes: vec![bbox],
+                color: [255, 255, 255],
+            };
+            let mut pixels = vec![[0u8; 3]; (width * height) as usize];
+            overlay.draw(&mut pixels, width, height);
+            pixels
+        };
+        let unit = Aabb::new(
+            na::Point3::new(-1.0, -1.0, -1.0),
+            na::Point3::new(1.0, 1.0, 1.0),
+        );
+        let pixels = draw(unit);
+        // The front face, 2 away, rises at a slope of 1/2 against tan 30 at
+        // the top of the viewport, which is 16 pixels above the centre.
+        let half = 16.0 * 0.5 / 30f32.to_radians().tan();
+        let top = (16.5 - half).round() as usize;
+        assert_eq!(pixels[top * width as usize + 16], [255, 255, 255]);
+        assert_eq!(pixels[16 * width as usize + 16], [0, 0, 0]);
+
+        let behind = Aabb::new(
+            na::Point3::new(-1.0, -1.0, 4.0),
+            na::Point3::new(1.0, 1.0, 6.0),
+        );
+        assert!(draw(behind).iter().all(|p| *p == [0, 0, 0]));
+    }
+}